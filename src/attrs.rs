@@ -0,0 +1,532 @@
+//! Loading the node attributes from files before running the tasks,
+//! and writing them out after
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use nadi_core::attrs::{Attribute, Date, HasAttributes};
+use nadi_core::network::Network;
+use serde_json::{Map, Number, Value};
+
+use crate::cli::NodeOrder;
+use crate::{network, table};
+
+/// Number of node names listed in the warnings before `...`
+const MAX_LISTED: usize = 10;
+
+/// The `.toml` and `.json` files in the attributes directory, sorted
+pub fn dir_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    entries.retain(|p| {
+        p.file_stem().is_some_and(|s| s.to_str().is_some())
+            && matches!(
+                p.extension().and_then(|s| s.to_str()),
+                Some("toml" | "json")
+            )
+    });
+    entries.sort();
+    Ok(entries)
+}
+
+/// Set the node attributes from the `DIR/{NAME}.toml` or
+/// `DIR/{NAME}.json` file of each node
+pub fn load_dir(net: &Network, dir: &Path) -> anyhow::Result<()> {
+    let mut matched = HashSet::new();
+    for path in dir_files(dir)? {
+        let (Some(name), Some(ext)) = (
+            path.file_stem().and_then(|s| s.to_str()),
+            path.extension().and_then(|s| s.to_str()),
+        ) else {
+            continue;
+        };
+        let Some(node) = net.node_by_name(name) else {
+            eprintln!(
+                "Warning: {}: no node named `{name}` in the network",
+                path.display()
+            );
+            continue;
+        };
+        let mut node = node.lock();
+        if ext == "toml" {
+            node.load_attr(&path)
+                .map_err(|e| anyhow::Error::msg(format!("{}: {e}", path.display())))?;
+        } else {
+            let txt = std::fs::read_to_string(&path)?;
+            let Value::Object(obj) = serde_json::from_str(&txt)
+                .map_err(|e| anyhow::Error::msg(format!("{}: {e}", path.display())))?
+            else {
+                anyhow::bail!("{}: attributes should be a JSON object", path.display());
+            };
+            for (key, val) in &obj {
+                if let Some(attr) = from_json(val) {
+                    node.set_attr(key, attr);
+                }
+            }
+        }
+        matched.insert(name.to_string());
+    }
+
+    let missing: Vec<String> = net
+        .nodes()
+        .map(|n| n.lock().name().to_string())
+        .filter(|n| !matched.contains(n))
+        .collect();
+    if !missing.is_empty() {
+        eprintln!(
+            "Warning: {} node(s) have no attributes file in {}: {}",
+            missing.len(),
+            dir.display(),
+            name_list(&missing)
+        );
+    }
+    Ok(())
+}
+
+/// Type of a table column, inferred from all of its non-empty values
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnType {
+    Int,
+    Float,
+    Bool,
+    Date,
+    String,
+}
+
+impl ColumnType {
+    fn infer<'a>(mut values: impl Iterator<Item = &'a str> + Clone) -> Self {
+        // IDs like USGS gauge numbers have leading zeros to keep
+        let numeric = values.clone().next().is_some()
+            && !values.clone().any(|v| {
+                let digits = v.trim_start_matches(['-', '+']);
+                digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.")
+            });
+        if numeric && values.clone().all(|v| v.parse::<i64>().is_ok()) {
+            Self::Int
+        } else if numeric && values.clone().all(|v| v.parse::<f64>().is_ok()) {
+            Self::Float
+        } else if values.clone().next().is_none() {
+            Self::String
+        } else if values.clone().all(|v| parse_bool(v).is_some()) {
+            Self::Bool
+        } else if values.all(|v| v.parse::<Date>().is_ok()) {
+            Self::Date
+        } else {
+            Self::String
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::Bool => "bool",
+            Self::Date => "date",
+            Self::String => "string",
+        }
+    }
+
+    /// Attribute from a value of the column, the value has already
+    /// been checked to be of this type
+    fn attribute(self, val: &str) -> Option<Attribute> {
+        Some(match self {
+            Self::Int => Attribute::Integer(val.parse().ok()?),
+            Self::Float => Attribute::Float(val.parse().ok()?),
+            Self::Bool => Attribute::Bool(parse_bool(val)?),
+            Self::Date => Attribute::Date(val.parse().ok()?),
+            Self::String => Attribute::String(val.into()),
+        })
+    }
+}
+
+fn parse_bool(val: &str) -> Option<bool> {
+    match val.to_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Set the node attributes from the columns of a CSV/TSV table, the
+/// rows are matched to the nodes by the names in the `key` column
+pub fn load_table(net: &Network, path: &Path, key: &str) -> anyhow::Result<()> {
+    let txt = std::fs::read_to_string(path)?;
+    let table = table::read(&txt, table::delimiter(path))
+        .map_err(|e| anyhow::Error::msg(format!("{}: {e}", path.display())))?;
+    let Some(key_col) = table.column(key) else {
+        anyhow::bail!(
+            "{}: no key column `{key}`, the columns are: {}",
+            path.display(),
+            table.header.join(", ")
+        );
+    };
+    let columns: Vec<(usize, ColumnType)> = (0..table.header.len())
+        .filter(|&c| c != key_col)
+        .map(|c| {
+            let values = table
+                .rows
+                .iter()
+                .map(move |(_, row)| row[c].as_str())
+                .filter(|v| !v.is_empty());
+            (c, ColumnType::infer(values))
+        })
+        .collect();
+
+    let mut unmatched = 0;
+    for (line, row) in &table.rows {
+        let Some(node) = net.node_by_name(&row[key_col]) else {
+            eprintln!(
+                "Warning: {}:{line}: no node named `{}` in the network",
+                path.display(),
+                row[key_col]
+            );
+            unmatched += 1;
+            continue;
+        };
+        let mut node = node.lock();
+        for &(c, ty) in columns.iter().filter(|(c, _)| !row[*c].is_empty()) {
+            if let Some(attr) = ty.attribute(&row[c]) {
+                node.set_attr(&table.header[c], attr);
+            }
+        }
+    }
+    let types: Vec<String> = columns
+        .iter()
+        .map(|&(c, ty)| format!("{} ({})", table.header[c], ty.name()))
+        .collect();
+    eprintln!(
+        "Loaded {} of {} row(s) from {}: {}",
+        table.rows.len() - unmatched,
+        table.rows.len(),
+        path.display(),
+        types.join(", ")
+    );
+    Ok(())
+}
+
+/// Attribute from the JSON value; `null` has no attribute equivalent
+pub fn from_json(val: &Value) -> Option<Attribute> {
+    Some(match val {
+        Value::Null => return None,
+        Value::Bool(b) => Attribute::Bool(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Attribute::Integer(i),
+            None => Attribute::Float(n.as_f64()?),
+        },
+        Value::String(s) => Attribute::String(s.as_str().into()),
+        Value::Array(vals) => {
+            Attribute::Array(vals.iter().filter_map(from_json).collect::<Vec<_>>().into())
+        }
+        Value::Object(obj) => Attribute::Table(
+            obj.iter()
+                .filter_map(|(k, v)| Some((k.as_str().into(), from_json(v)?)))
+                .collect(),
+        ),
+    })
+}
+
+/// JSON value of the attribute; dates and times become strings as
+/// JSON has no such type
+pub fn to_json(attr: &Attribute) -> Value {
+    match attr {
+        Attribute::Bool(b) => Value::Bool(*b),
+        Attribute::Integer(i) => Value::Number((*i).into()),
+        Attribute::Float(f) => Number::from_f64(*f).map_or(Value::Null, Value::Number),
+        Attribute::String(s) => Value::String(s.to_string()),
+        Attribute::Array(vals) => Value::Array(vals.iter().map(to_json).collect()),
+        Attribute::Table(tbl) => Value::Object(
+            tbl.keys()
+                .filter_map(|k| Some((k.to_string(), to_json(tbl.get(k)?))))
+                .collect(),
+        ),
+        _ => Value::String(attr.to_string()),
+    }
+}
+
+/// Attribute value as text: strings without the quotes, and the
+/// others as they are written in the tasks
+pub fn value_text(attr: &Attribute) -> String {
+    match attr {
+        Attribute::String(s) => s.to_string(),
+        attr => attr.to_string(),
+    }
+}
+
+/// Attributes of a node to export, with `NAME` and `INDEX`
+struct Row {
+    name: String,
+    index: usize,
+    attrs: HashMap<String, Attribute>,
+}
+
+/// Write the node attributes as a table with one row per node, the
+/// format is chosen by the file extension: `.csv`, `.tsv`, `.json` or
+/// `.parquet`
+pub fn export(
+    net: &Network,
+    path: &Path,
+    columns: &[String],
+    order: NodeOrder,
+) -> anyhow::Result<()> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    let mut rows: Vec<Row> = net
+        .nodes()
+        .map(|node| {
+            let node = node.lock();
+            Row {
+                name: node.name().to_string(),
+                index: node.index(),
+                attrs: node
+                    .attr_map()
+                    .keys()
+                    .filter_map(|k| Some((k.to_string(), node.attr(k)?.clone())))
+                    .collect(),
+            }
+        })
+        .collect();
+    sort_rows(net, &mut rows, order);
+
+    let columns: Vec<String> = if columns.is_empty() {
+        let attrs: BTreeSet<&String> = rows.iter().flat_map(|r| r.attrs.keys()).collect();
+        ["NAME", "INDEX"]
+            .into_iter()
+            .map(String::from)
+            .chain(attrs.into_iter().cloned())
+            .collect()
+    } else {
+        columns.to_vec()
+    };
+    report_mixed_types(&rows, &columns);
+
+    match ext.as_str() {
+        "csv" => std::fs::write(path, to_table(&rows, &columns, ','))?,
+        "tsv" | "tab" => std::fs::write(path, to_table(&rows, &columns, '\t'))?,
+        "json" => std::fs::write(path, format!("{:#}\n", to_records(&rows, &columns)))?,
+        "parquet" => write_parquet(&rows, &columns, path)?,
+        _ => anyhow::bail!(
+            "Unknown format for {}, use `.csv`, `.tsv`, `.json` or `.parquet`",
+            path.display()
+        ),
+    }
+    eprintln!(
+        "Exported {} column(s) of {} node(s) to {}",
+        columns.len(),
+        rows.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Sort the rows by the index, or by the distance from the outlet:
+/// headwaters first so each node comes after its inputs (`upstream`),
+/// or the outlet first so each node comes before them (`downstream`)
+fn sort_rows(net: &Network, rows: &mut [Row], order: NodeOrder) {
+    let nodes = network::nodes(net);
+    let graph = network::Graph::from_nodes(&nodes);
+    // outlets first, so the downstream node already has its distance
+    let mut distance = vec![0; nodes.len()];
+    for n in graph.upstream_first().into_iter().rev() {
+        if let Some(out) = graph.output[n] {
+            distance[n] = distance[out] + 1;
+        }
+    }
+    let distance = |row: &Row| graph.node(&row.name).map_or(0, |n| distance[n]);
+    match order {
+        NodeOrder::Index => rows.sort_by_key(|r| r.index),
+        NodeOrder::Upstream => rows.sort_by_key(|r| (std::cmp::Reverse(distance(r)), r.index)),
+        NodeOrder::Downstream => rows.sort_by_key(|r| (distance(r), r.index)),
+    }
+}
+
+/// Warn about the columns with values of different types, they are
+/// written as they are instead of being converted to one type
+fn report_mixed_types(rows: &[Row], columns: &[String]) {
+    for col in columns {
+        let mut types: BTreeMap<&str, usize> = BTreeMap::new();
+        for attr in rows.iter().filter_map(|r| r.attrs.get(col)) {
+            *types.entry(type_name(attr)).or_default() += 1;
+        }
+        if types.len() > 1 {
+            let types: Vec<String> = types
+                .iter()
+                .map(|(t, n)| format!("{t} ({n} nodes)"))
+                .collect();
+            eprintln!(
+                "Warning: column `{col}` has mixed types: {}",
+                types.join(", ")
+            );
+        }
+    }
+}
+
+fn type_name(attr: &Attribute) -> &'static str {
+    match attr {
+        Attribute::Bool(_) => "bool",
+        Attribute::String(_) => "string",
+        Attribute::Integer(_) => "int",
+        Attribute::Float(_) => "float",
+        Attribute::Date(_) => "date",
+        Attribute::Time(_) => "time",
+        Attribute::DateTime(_) => "datetime",
+        Attribute::Array(_) => "array",
+        Attribute::Table(_) => "table",
+    }
+}
+
+/// Value of the column as text, see [`value_text`]
+fn cell(row: &Row, col: &str) -> Option<String> {
+    match col {
+        "NAME" => Some(row.name.clone()),
+        "INDEX" => Some(row.index.to_string()),
+        _ => row.attrs.get(col).map(value_text),
+    }
+}
+
+fn to_table(rows: &[Row], columns: &[String], delim: char) -> String {
+    let quote = |field: &str| table::quote(field, delim);
+    let mut out = String::new();
+    let header: Vec<String> = columns.iter().map(|c| quote(c)).collect();
+    out.push_str(&header.join(&delim.to_string()));
+    out.push('\n');
+    for row in rows {
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|c| cell(row, c).map(|v| quote(&v)).unwrap_or_default())
+            .collect();
+        out.push_str(&fields.join(&delim.to_string()));
+        out.push('\n');
+    }
+    out
+}
+
+/// Array of one object per node, with `null` for the missing values
+fn to_records(rows: &[Row], columns: &[String]) -> Value {
+    let records = rows
+        .iter()
+        .map(|row| {
+            let obj: Map<String, Value> = columns
+                .iter()
+                .map(|col| {
+                    let val = match col.as_str() {
+                        "NAME" => Value::String(row.name.clone()),
+                        "INDEX" => Value::Number(row.index.into()),
+                        _ => row.attrs.get(col).map_or(Value::Null, to_json),
+                    };
+                    (col.clone(), val)
+                })
+                .collect();
+            Value::Object(obj)
+        })
+        .collect();
+    Value::Array(records)
+}
+
+/// Write the rows as a parquet file; the columns with values of a
+/// single type (int, float or bool) keep it, the others are written
+/// as text like in the CSV
+#[cfg(feature = "parquet")]
+fn write_parquet(rows: &[Row], columns: &[String], path: &Path) -> anyhow::Result<()> {
+    use std::sync::Arc;
+
+    use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+    use parquet::arrow::ArrowWriter;
+
+    let arrays = columns.iter().map(|col| {
+        let values = || rows.iter().map(|r| r.attrs.get(col));
+        let present: Vec<&Attribute> = values().flatten().collect();
+        let single = present
+            .windows(2)
+            .all(|w| type_name(w[0]) == type_name(w[1]));
+        let array: ArrayRef = match (col.as_str(), present.first()) {
+            ("NAME", _) => Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|r| r.name.as_str()),
+            )),
+            ("INDEX", _) => Arc::new(Int64Array::from_iter_values(
+                rows.iter().map(|r| r.index as i64),
+            )),
+            (_, Some(Attribute::Integer(_))) if single => {
+                Arc::new(Int64Array::from_iter(values().map(|v| match v {
+                    Some(Attribute::Integer(i)) => Some(*i),
+                    _ => None,
+                })))
+            }
+            (_, Some(Attribute::Float(_))) if single => {
+                Arc::new(Float64Array::from_iter(values().map(|v| match v {
+                    Some(Attribute::Float(f)) => Some(*f),
+                    _ => None,
+                })))
+            }
+            (_, Some(Attribute::Bool(_))) if single => {
+                Arc::new(BooleanArray::from_iter(values().map(|v| match v {
+                    Some(Attribute::Bool(b)) => Some(*b),
+                    _ => None,
+                })))
+            }
+            _ => Arc::new(StringArray::from_iter(values().map(|v| v.map(value_text)))),
+        };
+        (col, array)
+    });
+    let batch = RecordBatch::try_from_iter(arrays)?;
+    let file = std::fs::File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(_rows: &[Row], _columns: &[String], path: &Path) -> anyhow::Result<()> {
+    anyhow::bail!(
+        "Cannot write {}, nadi was built without the `parquet` feature",
+        path.display()
+    )
+}
+
+/// Comma separated names, with only the first few listed
+fn name_list(names: &[String]) -> String {
+    let mut list = names
+        .iter()
+        .take(MAX_LISTED)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if names.len() > MAX_LISTED {
+        list.push_str(", ...");
+    }
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(values: &[&str]) -> ColumnType {
+        ColumnType::infer(values.iter().copied())
+    }
+
+    #[test]
+    fn column_types() {
+        assert_eq!(infer(&["1", "-2", "+3"]), ColumnType::Int);
+        assert_eq!(infer(&["1", "0.5", "-0"]), ColumnType::Float);
+        assert_eq!(infer(&["0", "-0"]), ColumnType::Int);
+        assert_eq!(infer(&["true", "FALSE"]), ColumnType::Bool);
+        assert_eq!(infer(&["2020-01-02", "2021-12-31"]), ColumnType::Date);
+        assert_eq!(infer(&["1", "true"]), ColumnType::String);
+        assert_eq!(infer(&["ohio", "1"]), ColumnType::String);
+        assert_eq!(infer(&[]), ColumnType::String);
+    }
+
+    #[test]
+    fn leading_zeros() {
+        // IDs like gauge numbers keep their zeros as strings
+        assert_eq!(infer(&["01234", "5"]), ColumnType::String);
+        assert_eq!(infer(&["-012"]), ColumnType::String);
+        assert_eq!(infer(&["0.5", "0"]), ColumnType::Float);
+        assert_eq!(infer(&["00.5"]), ColumnType::String);
+    }
+}
@@ -0,0 +1,345 @@
+//! Check a tasks file for problems without running it
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use nadi_core::functions::{FuncArg, FuncArgType, NadiFunctions};
+use nadi_core::network::Network;
+use nadi_core::tasks::{Task, TaskInput, TaskType};
+
+use crate::cli::CheckArgs;
+use crate::diagnostic::Diagnostic;
+use crate::source::{self, CallArg, CallType, Statement};
+
+/// Function call of a parsed task, with the offsets of its parts in
+/// the statement text where they can be found
+struct Call<'a> {
+    ty: CallType,
+    name: &'a str,
+    /// Offset of the function name, the start of the statement if it
+    /// was not found
+    offset: usize,
+    args: Vec<CallArg<'a>>,
+}
+
+impl<'a> Call<'a> {
+    /// Call in the task, if it is a node or network function call. The
+    /// arguments are from the parsed task; their offsets are from the
+    /// same call in the source text when it has the same arguments,
+    /// otherwise they all point to the function name
+    fn from_task(task: &'a Task, stmt: &Statement<'a>) -> Option<Self> {
+        let ty = match task.ty {
+            TaskType::Node(_) => CallType::Node,
+            TaskType::Network => CallType::Network,
+            _ => return None,
+        };
+        let TaskInput::Function(ref func) = task.input else {
+            return None;
+        };
+        let name = func.name.as_str();
+        let found = stmt
+            .function_call()
+            .filter(|c| c.ty == ty && c.name == name);
+        let offset = found.as_ref().map(|c| c.offset).unwrap_or_default();
+        let mut keywords: Vec<&str> = func.kwargs.keys().map(|k| k.as_str()).collect();
+        keywords.sort();
+        let same_args = found.as_ref().is_some_and(|c| {
+            let mut kw: Vec<&str> = c
+                .args
+                .iter()
+                .filter_map(|a| match *a {
+                    CallArg::Keyword(_, k) => Some(k),
+                    CallArg::Positional(_) => None,
+                })
+                .collect();
+            let positional = c.args.len() - kw.len();
+            kw.sort();
+            kw.dedup();
+            positional == func.args.len() && kw == keywords
+        });
+        let args = match found {
+            Some(c) if same_args => c.args,
+            _ => std::iter::repeat_n(CallArg::Positional(offset), func.args.len())
+                .chain(keywords.into_iter().map(|k| CallArg::Keyword(offset, k)))
+                .collect(),
+        };
+        Some(Self {
+            ty,
+            name,
+            offset,
+            args,
+        })
+    }
+}
+
+/// A problem found in the tasks file
+struct Problem {
+    line: usize,
+    col: usize,
+    len: Option<usize>,
+    msg: String,
+}
+
+impl Problem {
+    fn at(stmt: &Statement, offset: usize, msg: String) -> Self {
+        let (line, col) = stmt.position(offset);
+        Self {
+            line,
+            col,
+            len: None,
+            msg,
+        }
+    }
+
+    /// Problem with the word at `offset`, only it is underlined
+    fn word(stmt: &Statement, offset: usize, word: &str, msg: String) -> Self {
+        Self {
+            len: Some(word.chars().count()),
+            ..Self::at(stmt, offset, msg)
+        }
+    }
+}
+
+pub fn check(args: &CheckArgs) -> anyhow::Result<()> {
+    let filename = args.tasks.to_string_lossy();
+    let net = args.network.load()?;
+    let mut checker = Checker {
+        net: net.as_ref(),
+        functions: NadiFunctions::new(),
+        files: source::OpenFiles::default(),
+        tasks: 0,
+        problems: 0,
+    };
+    let (full, txt) = checker
+        .files
+        .open(&args.tasks)
+        .map_err(anyhow::Error::msg)?;
+    checker.check_file(full, &args.tasks, &txt);
+    if checker.problems == 0 {
+        eprintln!("{filename}: {} tasks OK", checker.tasks);
+        Ok(())
+    } else {
+        anyhow::bail!("{} problem(s) found in {filename}", checker.problems)
+    }
+}
+
+/// Checks the tasks file and the files it includes
+struct Checker<'a> {
+    net: Option<&'a Network>,
+    functions: NadiFunctions,
+    /// Tasks files being checked, to find the include cycles
+    files: source::OpenFiles,
+    tasks: usize,
+    problems: usize,
+}
+
+impl Checker<'_> {
+    /// Check the tasks of the file, and the included files where they
+    /// are included; the files they include are relative to them
+    fn check_file(&mut self, full: PathBuf, path: &Path, txt: &str) {
+        let filename = path.to_string_lossy();
+        self.files.push(full, path);
+        let base = self.files.base().to_path_buf();
+        let mut problems = Vec::new();
+        for stmt in source::statements(txt) {
+            match stmt.include() {
+                Some(Ok(file)) => {
+                    let inc = base.join(file);
+                    match self.files.open(&inc) {
+                        Ok((full, included)) => {
+                            // the problems so far come before the ones
+                            // in the included file
+                            self.report(&filename, txt, &mut problems);
+                            self.check_file(full, &inc, &included);
+                        }
+                        Err(msg) => problems.push(Problem::at(&stmt, 0, msg)),
+                    }
+                    continue;
+                }
+                Some(Err(msg)) => {
+                    problems.push(Problem::at(&stmt, 0, msg));
+                    continue;
+                }
+                None => (),
+            }
+            let tasks = match stmt.parse() {
+                Ok(tasks) => tasks,
+                Err(err) => {
+                    problems.push(Problem {
+                        line: err.line,
+                        col: err.col,
+                        len: None,
+                        msg: err.msg,
+                    });
+                    continue;
+                }
+            };
+            self.tasks += tasks.len();
+            if let Some(net) = self.net {
+                check_selection(&stmt, net, &mut problems);
+            }
+            for task in &tasks {
+                if let Some(call) = Call::from_task(task, &stmt) {
+                    check_call(&stmt, &call, &self.functions, &mut problems);
+                }
+            }
+        }
+        self.report(&filename, txt, &mut problems);
+        self.files.pop();
+    }
+
+    fn report(&mut self, filename: &str, txt: &str, problems: &mut Vec<Problem>) {
+        for p in problems.drain(..) {
+            let diag = Diagnostic::new(&p.msg, filename, txt, p.line, p.col);
+            match p.len {
+                Some(len) => eprintln!("{}", diag.underline(len)),
+                None => eprintln!("{diag}"),
+            }
+            self.problems += 1;
+        }
+    }
+}
+
+/// Node names in the `node[...]` selection should be in the network
+fn check_selection(stmt: &Statement, net: &Network, problems: &mut Vec<Problem>) {
+    let Some(call) = stmt.function_call() else {
+        return;
+    };
+    for &(offset, name) in &call.selection {
+        if net.node_by_name(name).is_none() {
+            problems.push(Problem::word(
+                stmt,
+                offset,
+                name,
+                format!("Node `{name}` not found in the network"),
+            ));
+        }
+    }
+}
+
+fn check_call(
+    stmt: &Statement,
+    call: &Call,
+    functions: &NadiFunctions,
+    problems: &mut Vec<Problem>,
+) {
+    let (kind, signature) = match call.ty {
+        CallType::Node => (
+            "node",
+            functions.node_functions().get(call.name).map(|f| f.args()),
+        ),
+        CallType::Network => (
+            "network",
+            functions
+                .network_functions()
+                .get(call.name)
+                .map(|f| f.args()),
+        ),
+    };
+    let Some(signature) = signature else {
+        let mut msg = format!("Unknown {kind} function `{}`", call.name);
+        if let Some(similar) = similar_function(functions, call) {
+            msg.push_str(&format!(", did you mean `{similar}`?"));
+        }
+        problems.push(Problem::word(stmt, call.offset, call.name, msg));
+        return;
+    };
+    check_args(stmt, call, &signature, problems);
+}
+
+fn check_args(stmt: &Statement, call: &Call, signature: &[FuncArg], problems: &mut Vec<Problem>) {
+    let named: Vec<&str> = signature
+        .iter()
+        .filter(|a| {
+            matches!(
+                a.category,
+                FuncArgType::Arg | FuncArgType::OptArg | FuncArgType::DefArg(_)
+            )
+        })
+        .map(|a| a.name.as_str())
+        .collect();
+    let variadic = signature
+        .iter()
+        .any(|a| matches!(a.category, FuncArgType::Args));
+    let kw_variadic = signature
+        .iter()
+        .any(|a| matches!(a.category, FuncArgType::KwArgs));
+
+    let mut given = HashSet::new();
+    let mut positional = 0;
+    for arg in &call.args {
+        match *arg {
+            CallArg::Positional(offset) => {
+                if let Some(name) = named.get(positional) {
+                    given.insert(*name);
+                } else if !variadic {
+                    problems.push(Problem::at(
+                        stmt,
+                        offset,
+                        format!(
+                            "Function `{}` takes {} positional argument(s)",
+                            call.name,
+                            named.len()
+                        ),
+                    ));
+                }
+                positional += 1;
+            }
+            CallArg::Keyword(offset, name) => {
+                if !named.contains(&name) {
+                    if !kw_variadic {
+                        problems.push(Problem::word(
+                            stmt,
+                            offset,
+                            name,
+                            format!("Function `{}` has no argument `{name}`", call.name),
+                        ));
+                    }
+                } else if !given.insert(name) {
+                    problems.push(Problem::word(
+                        stmt,
+                        offset,
+                        name,
+                        format!("Argument `{name}` given more than once"),
+                    ));
+                }
+            }
+        }
+    }
+
+    for arg in signature {
+        if matches!(arg.category, FuncArgType::Arg) && !given.contains(arg.name.as_str()) {
+            problems.push(Problem::word(
+                stmt,
+                call.offset,
+                call.name,
+                format!(
+                    "Function `{}` is missing required argument `{}`",
+                    call.name, arg.name
+                ),
+            ));
+        }
+    }
+}
+
+/// Closest function name of the same type, to suggest for typos
+fn similar_function(functions: &NadiFunctions, call: &Call) -> Option<String> {
+    let names: Vec<String> = match call.ty {
+        CallType::Node => functions
+            .node_functions()
+            .keys()
+            .map(|k| k.to_string())
+            .collect(),
+        CallType::Network => functions
+            .network_functions()
+            .keys()
+            .map(|k| k.to_string())
+            .collect(),
+    };
+    names
+        .into_iter()
+        .map(|n| (strsim::levenshtein(call.name, &n), n))
+        .filter(|(d, _)| *d <= 2)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, n)| n)
+}
@@ -0,0 +1,490 @@
+//! Command line interface definitions
+
+use std::path::PathBuf;
+
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+
+use crate::network::export::ExportFormat;
+use crate::network::merge::{parse_join, Collision};
+use crate::network::NetworkFormat;
+
+#[derive(Default, Debug, Clone, ValueEnum)]
+pub enum FunctionType {
+    #[default]
+    Node,
+    Network,
+}
+
+/// How the results of the tasks are printed
+#[derive(Default, Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Print the results as they are
+    #[default]
+    Text,
+    /// Print a JSON array of all the tasks at the end
+    Json,
+    /// Print a JSON object per task as soon as it is run
+    Jsonl,
+}
+
+/// Order of the nodes in the exported tables
+#[derive(Default, Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum NodeOrder {
+    /// By the node index
+    #[default]
+    Index,
+    /// Headwaters first, each node after all of its inputs
+    Upstream,
+    /// Outlet first, each node before its inputs
+    Downstream,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+#[command(group(ArgGroup::new("legacy_action").multiple(false)))]
+pub struct CliArgs {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub legacy: LegacyArgs,
+}
+
+// parsed once at the start, boxing RunArgs would only make the
+// matches noisier
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run tasks from files, a string or stdin
+    Run(RunArgs),
+    /// Parse the tasks file and check the function calls without running it
+    Check(CheckArgs),
+    /// Show the tokens and parsed tasks of a tasks file
+    Show(ShowArgs),
+    /// Format a tasks file
+    Fmt(FmtArgs),
+    /// Query the available functions
+    Functions(FunctionsArgs),
+    /// Generate markdown doc for all plugins and functions
+    Doc(DocArgs),
+    /// Start an interactive shell
+    ///
+    /// `Ctrl-C` while a task runs can not stop that task: it finishes,
+    /// its result is shown and the rest of the input is skipped. Press
+    /// `Ctrl-C` again to exit without waiting for it.
+    Repl(ReplArgs),
+    /// Work with the network files
+    Network(NetworkCommandArgs),
+}
+
+#[derive(Args, Debug, Default)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub network: NetworkArgs,
+    /// Run given string as tasks
+    #[arg(short, long)]
+    pub task: Option<String>,
+    /// Set a value before running the tasks, e.g. `-D env.scenario=wet`;
+    /// strings need no quotes
+    #[arg(short = 'D', long, value_name = "TARGET=VALUE")]
+    pub define: Vec<String>,
+    /// TOML file of values to set as `env.NAME` before running the
+    /// tasks, tables become table values
+    #[arg(long)]
+    pub env_file: Option<PathBuf>,
+    /// Tasks file to run before the others, can be repeated
+    #[arg(long)]
+    pub prelude: Vec<PathBuf>,
+    /// Use stdin for the tasks; reads the whole stdin before execution
+    #[arg(short = 'S', long, action)]
+    pub stdin: bool,
+    /// print tasks before running them
+    #[arg(short, long)]
+    pub print_tasks: bool,
+    /// Keep running the other tasks when one fails, and summarize the
+    /// errors at the end
+    #[arg(short, long)]
+    pub keep_going: bool,
+    /// Stop after this many failed tasks in `--keep-going` mode
+    #[arg(long, requires = "keep_going")]
+    pub max_errors: Option<usize>,
+    /// Output format for the task results
+    #[arg(short, long, value_enum, default_value_t)]
+    pub output: OutputFormat,
+    /// Run again whenever a tasks file, the connections file or a
+    /// rendered template changes
+    #[arg(short, long, requires = "tasks", conflicts_with = "stdin")]
+    pub watch: bool,
+    /// Clear the screen before running again in `--watch` mode
+    #[arg(long, requires = "watch")]
+    pub clear: bool,
+    /// Print each task to stderr as it starts, with its index and line
+    #[arg(long)]
+    pub trace: bool,
+    /// Print the time taken by each task at the end
+    #[arg(long)]
+    pub profile: bool,
+    /// Run a `node` task on all the nodes as one `node[NAME]` task per
+    /// node, in the network order, with their outputs joined; so that
+    /// `--trace` shows and `--profile` times each node
+    #[arg(long)]
+    pub per_node: bool,
+    /// Write the task timings as Chrome trace events to this file
+    #[arg(long)]
+    pub profile_out: Option<PathBuf>,
+    /// Write the node attributes to this `.csv`, `.tsv`, `.json` or
+    /// `.parquet` file after the tasks have run; `.parquet` needs nadi
+    /// built with the `parquet` feature
+    #[arg(long, requires = "network")]
+    pub export_attrs: Option<PathBuf>,
+    /// Columns to export: `NAME`, `INDEX` or node attributes; all of
+    /// them if not given
+    #[arg(long, value_delimiter = ',', requires = "export_attrs")]
+    pub columns: Vec<String>,
+    /// Order of the nodes in the exported file
+    #[arg(long, value_enum, default_value_t, requires = "export_attrs")]
+    pub order: NodeOrder,
+    /// Run each tasks file in a new context, with the network loaded
+    /// again, instead of sharing the values set by the earlier files
+    #[arg(long)]
+    pub fresh: bool,
+    /// Tasks files to run in order; they run before `--task` and
+    /// `--stdin`
+    #[arg(required_unless_present_any = ["task", "stdin"])]
+    pub tasks: Vec<PathBuf>,
+    /// Values for the parameters declared in the tasks files, as
+    /// `--NAME VALUE`; `--help` lists them
+    #[arg(last = true, requires = "tasks")]
+    pub args: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// Network to check the node names used in the tasks against
+    #[command(flatten)]
+    pub network: NetworkArgs,
+    /// Tasks file to check
+    pub tasks: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct ShowArgs {
+    /// Tasks file to show
+    pub tasks: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct FmtArgs {
+    /// Check if the file is formatted, do not print anything
+    #[arg(long, conflicts_with = "in_place")]
+    pub check: bool,
+    /// Write the formatted tasks back to the file
+    #[arg(short, long)]
+    pub in_place: bool,
+    /// Tasks file to format
+    pub tasks: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct FunctionsArgs {
+    #[command(subcommand)]
+    pub command: FunctionsCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum FunctionsCommand {
+    /// list all functions
+    List {
+        /// only print the names of this type of functions, for completions
+        #[arg(short, long)]
+        names: Option<FunctionType>,
+    },
+    /// print help for a function
+    Help { function: String },
+    /// print code for a function
+    Code { function: String },
+}
+
+#[derive(Args, Debug)]
+pub struct DocArgs {
+    /// Directory to write the markdown docs into
+    pub dir: PathBuf,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct ReplArgs {
+    #[command(flatten)]
+    pub network: NetworkArgs,
+}
+
+#[derive(Args, Debug, Default, Clone)]
+pub struct NetworkArgs {
+    /// Network file: connections file, or one of the other formats;
+    /// repeat to merge several networks into one
+    #[arg(short, long)]
+    pub network: Vec<PathBuf>,
+    /// Format of the network file, guessed from the extension if not given
+    #[arg(long, value_enum, requires = "network")]
+    pub network_format: Option<NetworkFormat>,
+    #[command(flatten)]
+    pub merge: MergeOptions,
+    /// Directory with a `{NAME}.toml` or `{NAME}.json` file of
+    /// attributes for each node
+    #[arg(long, requires = "network")]
+    pub attributes: Option<PathBuf>,
+    /// CSV/TSV table of node attributes, one row per node
+    #[arg(long, requires = "network")]
+    pub attr_table: Option<PathBuf>,
+    /// Column of `--attr-table` with the node names
+    #[arg(long, default_value = "NAME", requires = "attr_table")]
+    pub key: String,
+}
+
+#[derive(Args, Debug, Default, Clone)]
+pub struct MergeOptions {
+    /// Connect the outlet of one network to a node of another, as
+    /// `OUTLET=NODE`; the names are the ones after `--on-collision`
+    #[arg(long, value_parser = parse_join)]
+    pub join: Vec<(String, String)>,
+    /// What to do when a node flows into different nodes in two networks
+    #[arg(long, value_enum, default_value_t)]
+    pub on_collision: Collision,
+}
+
+#[derive(Args, Debug)]
+pub struct NetworkCommandArgs {
+    #[command(subcommand)]
+    pub command: NetworkCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum NetworkCommand {
+    /// Convert the network to another file format
+    Convert(ConvertArgs),
+    /// Check the network file for problems like cycles, duplicate
+    /// edges or similar node names
+    Check(NetworkCheckArgs),
+    /// Show the network as a tree from the outlet up
+    Tree(TreeArgs),
+    /// Print the node counts, stream orders and other statistics
+    Stats(StatsArgs),
+    /// Write the connections file for a part of the network
+    Extract(ExtractArgs),
+    /// Show the nodes and links that changed between two networks
+    Diff(DiffArgs),
+    /// Merge several networks into one
+    Merge(MergeArgs),
+}
+
+/// Network file given to the `network` subcommands
+#[derive(Args, Debug)]
+pub struct NetworkFileArgs {
+    /// Network file
+    pub file: PathBuf,
+    /// Format of the network file, guessed from the extension if not given
+    #[arg(short, long, value_enum)]
+    pub format: Option<NetworkFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct ConvertArgs {
+    #[command(flatten)]
+    pub input: NetworkFileArgs,
+    /// Format to convert to
+    #[arg(short, long, value_enum)]
+    pub to: ExportFormat,
+    /// Output file, prints to stdout if not given
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct NetworkCheckArgs {
+    #[command(flatten)]
+    pub input: NetworkFileArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct TreeArgs {
+    #[command(flatten)]
+    pub input: NetworkFileArgs,
+    /// Columns to show after the tree: `NAME`, `INDEX` or node
+    /// attributes
+    #[arg(short, long, value_delimiter = ',')]
+    pub attrs: Vec<String>,
+    /// Levels of upstream nodes to show, all if not given
+    #[arg(short, long)]
+    pub depth: Option<usize>,
+    /// Start the tree from this node instead of the outlet
+    #[arg(long)]
+    pub from: Option<String>,
+    /// Draw the branches with ASCII characters
+    #[arg(long)]
+    pub ascii: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    pub input: NetworkFileArgs,
+    /// Print the statistics as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("selection").required(true).multiple(false)))]
+pub struct ExtractArgs {
+    #[command(flatten)]
+    pub input: NetworkFileArgs,
+    /// The node and all the nodes upstream of it
+    #[arg(long, group = "selection")]
+    pub upstream_of: Option<String>,
+    /// The node and all the nodes downstream of it
+    #[arg(long, group = "selection")]
+    pub downstream_of: Option<String>,
+    /// Nodes in the path from the first node down to the second
+    #[arg(long, num_args = 2, value_names = ["START", "END"], group = "selection")]
+    pub between: Vec<String>,
+    /// Nodes selected as in `node[...]`, e.g. `"a -> b"` or `"a, b"`
+    #[arg(long, group = "selection")]
+    pub select: Option<String>,
+    /// Output file, prints to stdout if not given
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// Old network file
+    pub old: PathBuf,
+    /// New network file
+    pub new: PathBuf,
+    /// Format of the network files, guessed from the extensions if not given
+    #[arg(short, long, value_enum)]
+    pub format: Option<NetworkFormat>,
+    /// Print the differences as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct MergeArgs {
+    /// Network files to merge, nodes with the same name are joined
+    #[arg(num_args = 2.., required = true)]
+    pub files: Vec<PathBuf>,
+    /// Format of the network files, guessed from the extensions if not given
+    #[arg(short, long, value_enum)]
+    pub format: Option<NetworkFormat>,
+    #[command(flatten)]
+    pub merge: MergeOptions,
+    /// Format of the merged network
+    #[arg(short, long, value_enum, default_value = "connections")]
+    pub to: ExportFormat,
+    /// Output file, prints to stdout if not given
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Flags from before the subcommands were added, these are kept so
+/// the old invocations keep working.
+#[derive(Args, Debug)]
+pub struct LegacyArgs {
+    /// Generate markdown doc for all plugins and functions
+    #[arg(short, long, hide = true, group = "legacy_action")]
+    generate_doc: Option<PathBuf>,
+    /// list all functions and exit
+    #[arg(short, long, hide = true, group = "legacy_action")]
+    list_functions: bool,
+    /// list all functions and exit for completions
+    #[arg(short = 'C', long, hide = true, group = "legacy_action")]
+    completion: Option<FunctionType>,
+    /// print help for a function
+    #[arg(short, long, hide = true, group = "legacy_action")]
+    fnhelp: Option<String>,
+    /// print code for a function
+    #[arg(short = 'c', long, hide = true, group = "legacy_action")]
+    fncode: Option<String>,
+    /// print tasks file and exit
+    #[arg(short, long)]
+    print_tasks: bool,
+    /// connections file
+    #[arg(short, long)]
+    network: Option<PathBuf>,
+    /// Run given string as tasks
+    #[arg(short, long)]
+    task: Option<String>,
+    /// Tasks file to run; if `--stdin` is also provided runs this before stdin
+    ///
+    /// Starts an interactive shell when no tasks are given
+    tasks: Option<PathBuf>,
+    /// Show the tasks file, do not do anything
+    #[arg(
+        short,
+        long,
+        action,
+        requires = "tasks",
+        hide = true,
+        group = "legacy_action"
+    )]
+    show: bool,
+    /// Use stdin for the tasks; reads the whole stdin before execution
+    #[arg(short = 'S', long, action)]
+    stdin: bool,
+}
+
+impl LegacyArgs {
+    /// Convert the old flags into the equivalent subcommand
+    pub fn into_command(self) -> Command {
+        let (cmd, replacement) = if self.show {
+            let tasks = self.tasks.expect("clap makes --show require tasks");
+            (Command::Show(ShowArgs { tasks }), "show")
+        } else if let Some(dir) = self.generate_doc {
+            (Command::Doc(DocArgs { dir }), "doc")
+        } else if let Some(function) = self.fnhelp {
+            (
+                functions(FunctionsCommand::Help { function }),
+                "functions help",
+            )
+        } else if let Some(function) = self.fncode {
+            (
+                functions(FunctionsCommand::Code { function }),
+                "functions code",
+            )
+        } else if self.list_functions {
+            (
+                functions(FunctionsCommand::List { names: None }),
+                "functions list",
+            )
+        } else if let Some(ty) = self.completion {
+            (
+                functions(FunctionsCommand::List { names: Some(ty) }),
+                "functions list --names",
+            )
+        } else if self.tasks.is_none() && self.task.is_none() && !self.stdin {
+            return Command::Repl(ReplArgs {
+                network: NetworkArgs {
+                    network: self.network.into_iter().collect(),
+                    ..Default::default()
+                },
+            });
+        } else {
+            return Command::Run(RunArgs {
+                network: NetworkArgs {
+                    network: self.network.into_iter().collect(),
+                    ..Default::default()
+                },
+                task: self.task,
+                stdin: self.stdin,
+                print_tasks: self.print_tasks,
+                tasks: self.tasks.into_iter().collect(),
+                ..Default::default()
+            });
+        };
+        eprintln!("Warning: this flag is deprecated, use `nadi {replacement}` instead");
+        cmd
+    }
+}
+
+fn functions(command: FunctionsCommand) -> Command {
+    Command::Functions(FunctionsArgs { command })
+}
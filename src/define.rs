@@ -0,0 +1,85 @@
+//! Values set from the command line (`-D`) or an env file before the
+//! tasks run; the defines are assignment tasks so that nadi parses
+//! the values
+
+use std::path::Path;
+
+use nadi_core::attrs::{AttrMap, HasAttributes};
+use nadi_core::parser::tokenizer::{get_tokens, TaskToken};
+
+use crate::source::is_identifier;
+
+/// Assignment task for `-D TARGET=VALUE`; the value is used as is if
+/// it is a literal (number, string, date, array, table...), and as a
+/// string otherwise, so `-D env.scenario=wet` needs no quotes
+pub fn define_task(def: &str) -> anyhow::Result<String> {
+    let Some((target, value)) = def.split_once('=') else {
+        anyhow::bail!("Invalid define `{def}`, expected `env.NAME=VALUE`");
+    };
+    let target = target.trim();
+    let valid = ["env.", "network.", "node."]
+        .iter()
+        .any(|p| target.strip_prefix(p).is_some_and(is_identifier));
+    if !valid {
+        anyhow::bail!(
+            "Invalid define target `{target}`, expected `env.NAME`, `network.NAME` or `node.NAME`"
+        );
+    }
+    Ok(format!("{target} = {}", value_text(value.trim())))
+}
+
+/// Values of the env file, a TOML file read with the same loader as
+/// the node attribute files
+pub fn load_env_file(path: &Path) -> anyhow::Result<AttrMap> {
+    let mut env = EnvFile(AttrMap::default());
+    env.load_attr(path)
+        .map_err(|e| anyhow::Error::msg(format!("{}: {e}", path.display())))?;
+    Ok(env.0)
+}
+
+struct EnvFile(AttrMap);
+
+impl HasAttributes for EnvFile {
+    fn attr_map(&self) -> &AttrMap {
+        &self.0
+    }
+
+    fn attr_map_mut(&mut self) -> &mut AttrMap {
+        &mut self.0
+    }
+}
+
+/// The value as it is if it is a literal, a quoted string otherwise
+fn value_text(value: &str) -> String {
+    if is_literal(value) {
+        value.to_string()
+    } else {
+        format!("{value:?}")
+    }
+}
+
+/// Whether the tokenizer reads the value as a single literal token, or
+/// the start of an array or table (nadi parses the rest)
+pub fn is_literal(value: &str) -> bool {
+    let tokens = get_tokens(value).unwrap_or_default();
+    match tokens[..] {
+        [ref token] => matches!(
+            token.ty,
+            TaskToken::Bool
+                | TaskToken::String(_)
+                | TaskToken::Integer
+                | TaskToken::Float
+                | TaskToken::Date
+                | TaskToken::Time
+                | TaskToken::DateTime
+        ),
+        [ref first, ..] => matches!(first.ty, TaskToken::BracketStart | TaskToken::BraceStart),
+        [] => false,
+    }
+}
+
+/// Whether the tokenizer reads the value as a single quoted string
+pub fn is_string(value: &str) -> bool {
+    let tokens = get_tokens(value).unwrap_or_default();
+    matches!(tokens[..], [ref token] if matches!(token.ty, TaskToken::String(_)))
+}
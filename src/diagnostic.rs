@@ -0,0 +1,95 @@
+//! Error messages pointing into the tasks source, in the style of
+//! rustc:
+//!
+//! ```text
+//! error: Function `render` is missing required argument `template`
+//!   --> test.tasks:12:9
+//!    |
+//! 12 | network render()
+//!    |         ^^^^^^
+//! ```
+
+use std::fmt;
+
+use colored::Colorize;
+
+pub struct Diagnostic<'a> {
+    msg: &'a str,
+    file: &'a str,
+    source: &'a str,
+    line: usize,
+    col: usize,
+    len: Option<usize>,
+}
+
+impl<'a> Diagnostic<'a> {
+    /// Error at `line` and `col` (both 1-based) of the `source` text
+    /// read from `file`; underlines till the end of the line unless
+    /// [`Diagnostic::underline`] is given
+    pub fn new(msg: &'a str, file: &'a str, source: &'a str, line: usize, col: usize) -> Self {
+        Self {
+            msg,
+            file,
+            source,
+            line,
+            col,
+            len: None,
+        }
+    }
+
+    /// Number of characters to underline
+    pub fn underline(mut self, len: usize) -> Self {
+        self.len = Some(len);
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut msg = self.msg.lines();
+        writeln!(
+            f,
+            "{}: {}",
+            "error".red().bold(),
+            msg.next().unwrap_or_default().bold()
+        )?;
+        let width = self.line.to_string().len();
+        writeln!(
+            f,
+            "{:width$}{} {}:{}:{}",
+            "",
+            "-->".blue().bold(),
+            self.file,
+            self.line,
+            self.col
+        )?;
+        let bar = "|".blue().bold();
+        let Some(src) = self.source.lines().nth(self.line.saturating_sub(1)) else {
+            return Ok(());
+        };
+        writeln!(f, "{:width$} {bar}", "")?;
+        writeln!(f, "{} {bar} {src}", self.line.to_string().blue().bold())?;
+        // keep the tabs so the carets line up with the source line
+        let padding: String = src
+            .chars()
+            .take(self.col.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let rest = src.chars().skip(self.col.saturating_sub(1)).count();
+        let len = self
+            .len
+            .unwrap_or_else(|| src.trim_end().chars().count().saturating_sub(padding.len()))
+            .min(rest)
+            .max(1);
+        writeln!(
+            f,
+            "{:width$} {bar} {padding}{}",
+            "",
+            "^".repeat(len).red().bold()
+        )?;
+        for line in msg {
+            writeln!(f, "{:width$} {bar} {line}", "")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+//! Formatting of the tasks files
+
+use crate::cli::FmtArgs;
+use crate::diagnostic::Diagnostic;
+use crate::source;
+
+pub fn fmt(args: &FmtArgs) -> anyhow::Result<()> {
+    let txt = std::fs::read_to_string(&args.tasks)?;
+    let formatted = format_tasks(&txt, &args.tasks.to_string_lossy())?;
+    if args.check {
+        if formatted != txt {
+            anyhow::bail!("{} is not formatted", args.tasks.display());
+        }
+    } else if args.in_place {
+        std::fs::write(&args.tasks, formatted)?;
+    } else {
+        print!("{formatted}");
+    }
+    Ok(())
+}
+
+/// Format each statement in the tasks source, the comment and blank
+/// lines in between are kept as they are. Statements with comments
+/// inside them are also kept as they are, as the parsed tasks do not
+/// have the comments, and so are the `include` directives.
+fn format_tasks(txt: &str, filename: &str) -> anyhow::Result<String> {
+    let lines: Vec<&str> = txt.lines().collect();
+    let mut out = String::with_capacity(txt.len());
+    let mut next = 0;
+    for stmt in source::statements(txt) {
+        for line in &lines[next..(stmt.line - 1)] {
+            out.push_str(line);
+            out.push('\n');
+        }
+        next = stmt.line - 1 + stmt.text.lines().count();
+        if stmt.has_comment() || stmt.include().is_some() {
+            out.push_str(stmt.text);
+            out.push('\n');
+            continue;
+        }
+        let tasks = stmt.parse().map_err(|e| {
+            anyhow::Error::msg(Diagnostic::new(&e.msg, filename, txt, e.line, e.col).to_string())
+        })?;
+        for task in tasks {
+            out.push_str(&task.to_string());
+            out.push('\n');
+        }
+    }
+    for line in &lines[next.min(lines.len())..] {
+        out.push_str(line);
+        out.push('\n');
+    }
+    Ok(out)
+}
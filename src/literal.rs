@@ -0,0 +1,299 @@
+//! Convert the attribute values printed by the tasks back into typed
+//! JSON values.
+//!
+//! [`TaskContext::execute`](nadi_core::tasks::TaskContext::execute)
+//! returns the results as text, which uses the same literal syntax
+//! as the tasks: booleans, numbers, strings, dates/times, arrays and
+//! tables. Dates and times become strings as JSON has no such type.
+
+use serde_json::{Map, Number, Value};
+
+/// Result text of a task as JSON; it is either a single value, or a
+/// list of `name = value` lines (e.g. one per node) that becomes an
+/// object. Anything else is kept as a string.
+pub fn result_to_json(txt: &str) -> Value {
+    if let Some(val) = parse(txt) {
+        return val;
+    }
+    let mut map = Map::new();
+    for line in txt.lines().filter(|l| !l.trim().is_empty()) {
+        let Some(Value::Object(obj)) = parse(&format!("{{{line}}}")) else {
+            return Value::String(txt.to_string());
+        };
+        map.extend(obj);
+    }
+    if map.is_empty() {
+        Value::String(txt.to_string())
+    } else {
+        Value::Object(map)
+    }
+}
+
+/// Parse a single literal value, the whole text should be the value
+pub fn parse(txt: &str) -> Option<Value> {
+    let mut parser = Parser { txt, pos: 0 };
+    let val = parser.value()?;
+    parser.skip_ws();
+    (parser.pos == txt.len()).then_some(val)
+}
+
+struct Parser<'a> {
+    txt: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.txt[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_ws();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume the characters matching `pred` and return them
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !pred(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_ws();
+        match self.peek()? {
+            '"' => self.string().map(Value::String),
+            '[' => self.array(),
+            '{' => self.table(),
+            _ => self.word(),
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.pos += 1;
+        let mut s = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Some(s);
+                }
+                '\\' => match chars.next()?.1 {
+                    'n' => s.push('\n'),
+                    't' => s.push('\t'),
+                    'r' => s.push('\r'),
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+        None
+    }
+
+    fn array(&mut self) -> Option<Value> {
+        self.pos += 1;
+        let mut vals = Vec::new();
+        loop {
+            if self.eat(']') {
+                break;
+            }
+            vals.push(self.value()?);
+            if self.eat(']') {
+                break;
+            } else if !self.eat(',') {
+                return None;
+            }
+        }
+        Some(Value::Array(vals))
+    }
+
+    fn table(&mut self) -> Option<Value> {
+        self.pos += 1;
+        let mut map = Map::new();
+        loop {
+            if self.eat('}') {
+                break;
+            }
+            self.skip_ws();
+            let key = match self.peek()? {
+                '"' => self.string()?,
+                _ => {
+                    let k = self.take_while(|c| c.is_alphanumeric() || "_-".contains(c));
+                    if k.is_empty() {
+                        return None;
+                    }
+                    k.to_string()
+                }
+            };
+            if !self.eat('=') {
+                return None;
+            }
+            map.insert(key, self.value()?);
+            if self.eat('}') {
+                break;
+            } else if !self.eat(',') {
+                return None;
+            }
+        }
+        Some(Value::Object(map))
+    }
+
+    /// Booleans, numbers, dates and times
+    fn word(&mut self) -> Option<Value> {
+        let word = self.take_while(|c| c.is_alphanumeric() || "_.:+-".contains(c));
+        match word {
+            "" => None,
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ if is_date(word) => {
+                // datetime can have a space between the date and time
+                let rest = self.rest();
+                let time = rest
+                    .strip_prefix(' ')
+                    .map(|r| {
+                        &r[..r
+                            .find(|c: char| !c.is_ascii_digit() && c != ':')
+                            .unwrap_or(r.len())]
+                    })
+                    .filter(|t| is_time(t));
+                match time {
+                    Some(t) => {
+                        self.pos += 1 + t.len();
+                        Some(Value::String(format!("{word} {t}")))
+                    }
+                    None => Some(Value::String(word.to_string())),
+                }
+            }
+            _ if is_time(word)
+                || word
+                    .split_once('T')
+                    .is_some_and(|(d, t)| is_date(d) && is_time(t)) =>
+            {
+                Some(Value::String(word.to_string()))
+            }
+            _ => {
+                if let Ok(i) = word.parse::<i64>() {
+                    Some(Value::Number(i.into()))
+                } else {
+                    word.parse::<f64>()
+                        .ok()
+                        .and_then(Number::from_f64)
+                        .map(Value::Number)
+                }
+            }
+        }
+    }
+}
+
+fn is_date(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('-').collect();
+    parts.len() == 3
+        && parts[0].len() == 4
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn is_time(s: &str) -> bool {
+    let parts: Vec<&str> = s.split(':').collect();
+    (2..=3).contains(&parts.len())
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn scalars() {
+        assert_eq!(result_to_json("true"), json!(true));
+        assert_eq!(result_to_json("42"), json!(42));
+        assert_eq!(result_to_json("-3"), json!(-3));
+        assert_eq!(result_to_json("2.5"), json!(2.5));
+        assert_eq!(result_to_json("1e3"), json!(1000.0));
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(result_to_json("\"ohio\""), json!("ohio"));
+        assert_eq!(
+            result_to_json(r#""a \"b\"\n\tc\\d""#),
+            json!("a \"b\"\n\tc\\d")
+        );
+        assert_eq!(result_to_json("\"unclosed"), json!("\"unclosed"));
+    }
+
+    #[test]
+    fn arrays_and_tables() {
+        assert_eq!(
+            result_to_json("[1, \"a\", [true, 2.5], []]"),
+            json!([1, "a", [true, 2.5], []])
+        );
+        assert_eq!(
+            result_to_json("{x = 1, \"y z\" = [2], w = {v = false}}"),
+            json!({"x": 1, "y z": [2], "w": {"v": false}})
+        );
+        assert_eq!(result_to_json("{}"), json!({}));
+    }
+
+    #[test]
+    fn dates_and_times() {
+        assert_eq!(result_to_json("2020-01-02"), json!("2020-01-02"));
+        assert_eq!(result_to_json("10:30"), json!("10:30"));
+        assert_eq!(result_to_json("10:30:15"), json!("10:30:15"));
+        assert_eq!(
+            result_to_json("2020-01-02T10:30:00"),
+            json!("2020-01-02T10:30:00")
+        );
+        assert_eq!(
+            result_to_json("2020-01-02 10:30:00"),
+            json!("2020-01-02 10:30:00")
+        );
+        assert_eq!(
+            result_to_json("[2020-01-02 10:30, 2020-01-03]"),
+            json!(["2020-01-02 10:30", "2020-01-03"])
+        );
+    }
+
+    #[test]
+    fn name_value_lines() {
+        assert_eq!(
+            result_to_json("a = 1\nb = \"x\"\n\nc = 2020-01-02 10:30\n"),
+            json!({"a": 1, "b": "x", "c": "2020-01-02 10:30"})
+        );
+        assert_eq!(
+            result_to_json("\"node-1\" = [1, 2]"),
+            json!({"node-1": [1, 2]})
+        );
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(result_to_json("hello world"), json!("hello world"));
+        assert_eq!(
+            result_to_json("a = 1\nnot a value"),
+            json!("a = 1\nnot a value")
+        );
+        assert_eq!(result_to_json("1 2"), json!("1 2"));
+        assert_eq!(result_to_json(""), json!(""));
+    }
+}
@@ -1,144 +1,202 @@
-use std::{io::Read, path::{PathBuf, Path}};
+use std::{io::Read, path::Path};
 use nadi_core::parser::NadiError;
-use clap::{Parser, ValueEnum};
-use nadi_core::{functions::NadiFunctions, network::Network};
+use clap::Parser;
+use nadi_core::functions::NadiFunctions;
 
-#[derive(Default, Debug, Clone, ValueEnum)]
-enum FunctionType {
-    #[default]
-    Node,
-    Network,
-}
+use cli::{CliArgs, Command, FunctionType, FunctionsCommand, RunArgs};
 
-#[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct CliArgs {
-    /// Generate markdown doc for all plugins and functions
-    #[arg(short, long)]
-    generate_doc: Option<PathBuf>,
-    /// list all functions and exit
-    #[arg(short, long)]
-    list_functions: bool,
-    /// list all functions and exit for completions
-    #[arg(short = 'C', long)]
-    completion: Option<FunctionType>,
-    /// print help for a function
-    #[arg(short, long)]
-    fnhelp: Option<String>,
-    /// print code for a function
-    #[arg(short = 'c', long)]
-    fncode: Option<String>,
-    /// print tasks file and exit
-    #[arg(short, long)]
-    print_tasks: bool,
-    /// connections file
-    #[arg(short, long)]
-    network: Option<PathBuf>,
-    #[arg(short, long)]
-    /// Run given string as tasks
-    task: Option<String>,
-    /// Tasks file to run; if `--stdin` is also provided runs this before stdin
-    tasks: Option<PathBuf>,
-    /// Show the tasks file, do not do anything
-    #[arg(short, long, action, requires="tasks")]
-    show: bool,
-    /// Use stdin for the tasks; reads the whole stdin before execution
-    #[arg(short='S', long, action)]
-    stdin: bool,
-}
+mod attrs;
+mod check;
+mod cli;
+mod define;
+mod diagnostic;
+mod fmt;
+mod literal;
+mod network;
+mod output;
+mod profile;
+mod repl;
+mod script;
+mod session;
+mod source;
+mod table;
+mod watch;
 
 fn main() -> anyhow::Result<()> {
-    let args = CliArgs::parse();
-    let functions = NadiFunctions::new();
-
-    if args.show {
-	show_tasks(&args.tasks.unwrap());
-    } else if let Some(dir) = args.generate_doc {
-        functions.plugins_doc(&dir)?;
-    } else if let Some(func) = args.fnhelp {
-        println!("{}", functions.help(&func).unwrap_or_default());
-    } else if let Some(func) = args.fncode {
-        println!("{}", functions.code(&func).unwrap_or_default());
-    } else if args.list_functions {
-        functions.list_functions();
-    } else if let Some(comp) = args.completion {
-        match comp {
-            FunctionType::Node => {
-                for f in functions.node_functions().keys() {
-                    println!("{f}");
-                }
-            }
-            FunctionType::Network => {
-                for f in functions.network_functions().keys() {
-                    println!("{f}");
-                }
+    let command = match script::command_from_args() {
+        Some(cmd) => cmd,
+        None => {
+            let args = CliArgs::parse();
+            match args.command {
+                Some(cmd) => cmd,
+                None => args.legacy.into_command(),
             }
         }
-    } else {
-        if let Some(ref tasks) = args.tasks {
-            let txt = std::fs::read_to_string(tasks)?;
-            execute_tasks(&functions, &txt, &args)?;
+    };
+
+    match command {
+        Command::Run(args) if args.watch => watch::watch(&args)?,
+        Command::Run(args) => run(&args)?,
+        Command::Check(args) => check::check(&args)?,
+        Command::Show(args) => show_tasks(&args.tasks)?,
+        Command::Fmt(args) => fmt::fmt(&args)?,
+        Command::Functions(args) => functions(args.command),
+        Command::Doc(args) => NadiFunctions::new().plugins_doc(&args.dir)?,
+        Command::Repl(args) => repl::run(args.network.load()?)?,
+        Command::Network(args) => network::run(args.command)?,
+    }
+    Ok(())
+}
+
+fn run(args: &RunArgs) -> anyhow::Result<()> {
+    let params = script::files_params(&args.tasks)?;
+    if args.args.iter().any(|a| a == "--help" || a == "-h") {
+        if let Some(tasks) = args.tasks.first() {
+            print!("{}", script::usage(tasks, &params));
         }
-        if let Some(ref txt) = args.task {
-            execute_tasks(&functions, txt, &args)?;
+        return Ok(());
+    }
+    let net = args.network.load()?;
+    // all the sources run in order in the same session, so whatever
+    // a tasks file sets is available to the later ones (unless
+    // `--fresh`)
+    let mut session = session::Session::new(net, args.print_tasks);
+    if args.keep_going {
+        session.keep_going(args.max_errors);
+    }
+    session.output(args.output);
+    if args.profile || args.profile_out.is_some() {
+        session.profile();
+    }
+    if args.trace {
+        session.trace();
+    }
+    if args.per_node {
+        session.per_node();
+    }
+    let result = run_sources(&mut session, args, &params);
+    session.finish();
+    if let Some(prof) = session.profiler() {
+        if args.profile {
+            prof.print_report();
         }
-        if args.stdin {
-            let mut txt = String::new();
-            std::io::stdin().read_to_string(&mut txt)?;
-            execute_tasks(&functions, &txt, &args)?;
+        if let Some(ref out) = args.profile_out {
+            prof.write_trace(out)?;
         }
     }
+    result?;
+    if let Some(ref path) = args.export_attrs {
+        attrs::export(session.network(), path, &args.columns, args.order)?;
+    }
+    if !session.errors().is_empty() {
+        anyhow::bail!("{} task(s) failed", session.errors().len());
+    }
     Ok(())
 }
 
-fn show_tasks(filename: &Path) {
-    let txt = std::fs::read_to_string(filename).unwrap();
-    match nadi_core::parser::tokenizer::get_tokens(&txt){
-        Ok(tokens) => {
-	    println!("\n----File Tokens----");
-            for tok in &tokens {
-                tok.colored_print();
-            }
-	    match nadi_core::parser::tasks::parse(tokens) {
-		Ok(tasks) => {
-		    println!("\n----Parsed Tasks----");
-		    for task in tasks {
-			println!("{}", task.to_colored_string());
-		    }
-		}
-		Err(e) => println!("{}", e.user_msg(Some(&filename.to_string_lossy()))),
-	    };
-	},
-        Err(e) => println!("{}", e.user_msg(Some(&filename.to_string_lossy()))),
+/// Values of the tasks file parameters, then from `--env-file` and
+/// `-D`, so the defines can override the others
+fn set_values(
+    session: &mut session::Session,
+    args: &RunArgs,
+    params: &[script::Param],
+) -> anyhow::Result<()> {
+    if let Some(tasks) = args.tasks.first() {
+        session.define("<params>", &script::param_tasks(tasks, params, &args.args)?)?;
+    }
+    if let Some(ref file) = args.env_file {
+        session.set_env(&define::load_env_file(file)?);
+    }
+    for def in &args.define {
+        session.define("-D", &define::define_task(def)?)?;
     }
+    Ok(())
 }
 
-fn execute_tasks(functions: &NadiFunctions, txt: &str, args: &CliArgs) -> anyhow::Result<()> {
-    let tokens = nadi_core::parser::tokenizer::get_tokens(&txt)?;
-    let tasks = match nadi_core::parser::tasks::parse(tokens) {
-	Ok(t) => t,
-	Err(e) => return Err(anyhow::Error::msg(e.user_msg(None))),
-    };
-    if args.print_tasks {
-        for fc in &tasks {
-            println!("{}", fc.to_colored_string());
+/// Set the values and run the preludes, for each new context
+fn prepare(
+    session: &mut session::Session,
+    args: &RunArgs,
+    params: &[script::Param],
+) -> anyhow::Result<()> {
+    set_values(session, args, params)?;
+    for prelude in &args.prelude {
+        session.run_prelude(prelude)?;
+    }
+    Ok(())
+}
+
+fn run_sources(
+    session: &mut session::Session,
+    args: &RunArgs,
+    params: &[script::Param],
+) -> anyhow::Result<()> {
+    prepare(session, args, params)?;
+    for (i, tasks) in args.tasks.iter().enumerate() {
+        if args.fresh && i > 0 {
+            session.reset(args.network.load()?);
+            prepare(session, args, params)?;
         }
+        session.run_file(tasks)?;
+    }
+    if let Some(ref txt) = args.task {
+        session.run("<task>", txt)?;
     }
+    if args.stdin {
+        let mut txt = String::new();
+        std::io::stdin().read_to_string(&mut txt)?;
+        session.run("<stdin>", &txt)?;
+    }
+    Ok(())
+}
 
-    let net = if let Some(ref net) = args.network {
-        Some(Network::from_file(net)?)
-    } else {
-        None
-    };
-    eprintln!("** Running {} Script **", tasks.len());
+fn functions(cmd: FunctionsCommand) {
+    let functions = NadiFunctions::new();
+    match cmd {
+        FunctionsCommand::List { names: None } => functions.list_functions(),
+        FunctionsCommand::List {
+            names: Some(FunctionType::Node),
+        } => {
+            for f in functions.node_functions().keys() {
+                println!("{f}");
+            }
+        }
+        FunctionsCommand::List {
+            names: Some(FunctionType::Network),
+        } => {
+            for f in functions.network_functions().keys() {
+                println!("{f}");
+            }
+        }
+        FunctionsCommand::Help { function } => {
+            println!("{}", functions.help(&function).unwrap_or_default())
+        }
+        FunctionsCommand::Code { function } => {
+            println!("{}", functions.code(&function).unwrap_or_default())
+        }
+    }
+}
 
-    let mut tasks_ctx = nadi_core::tasks::TaskContext::new(net);
-    for fc in tasks {
-        match tasks_ctx.execute(fc) {
-	    Ok(Some(p)) => println!("{p}"),
-	    Err(p) => return Err(anyhow::Error::msg(p)),
-	    _ => (),
-	}
+fn show_tasks(filename: &Path) -> anyhow::Result<()> {
+    let txt = std::fs::read_to_string(filename)?;
+    let name = filename.to_string_lossy();
+    match nadi_core::parser::tokenizer::get_tokens(&txt) {
+        Ok(tokens) => {
+            println!("\n----File Tokens----");
+            for tok in &tokens {
+                tok.colored_print();
+            }
+            match nadi_core::parser::tasks::parse(tokens) {
+                Ok(tasks) => {
+                    println!("\n----Parsed Tasks----");
+                    for task in tasks {
+                        println!("{}", task.to_colored_string());
+                    }
+                }
+                Err(e) => println!("{}", e.user_msg(Some(&name))),
+            };
+        }
+        Err(e) => println!("{}", e.user_msg(Some(&name))),
     }
     Ok(())
 }
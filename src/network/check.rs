@@ -0,0 +1,337 @@
+//! Checking a network file for problems in its edges, before they
+//! show up as odd results from the node functions
+
+use std::collections::{BTreeMap, HashMap};
+
+use colored::Colorize;
+
+use super::read::Edge;
+use super::Graph;
+use crate::cli::NetworkCheckArgs;
+use crate::diagnostic::Diagnostic;
+
+/// A problem found in the network file
+struct Problem {
+    line: Option<usize>,
+    msg: String,
+}
+
+impl Problem {
+    fn new(line: Option<usize>, msg: String) -> Self {
+        Self { line, msg }
+    }
+}
+
+pub fn check(args: &NetworkCheckArgs) -> anyhow::Result<()> {
+    let edges = args.input.edges()?;
+    let txt = std::fs::read_to_string(&args.input.file)?;
+    let filename = args.input.file.to_string_lossy();
+
+    let problems = find_problems(&edges);
+    for p in &problems {
+        match p.line {
+            Some(line) => eprintln!("{}", Diagnostic::new(&p.msg, &filename, &txt, line, 1)),
+            None => eprintln!(
+                "{}: {}\n  {} {filename}\n",
+                "error".red().bold(),
+                p.msg.as_str().bold(),
+                "-->".blue().bold()
+            ),
+        }
+    }
+    if problems.is_empty() {
+        eprintln!(
+            "{filename}: {} nodes, {} edges OK",
+            Graph::from_edges(&edges).names.len(),
+            edges.len()
+        );
+        Ok(())
+    } else {
+        anyhow::bail!("{} problem(s) found in {filename}", problems.len())
+    }
+}
+
+/// Problems in the edges, in the order of their lines
+fn find_problems(edges: &[Edge]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    check_edges(edges, &mut problems);
+    // each node only keeps its first downstream node, so the other
+    // checks are not repeated for the problems check_edges found
+    let graph = Graph::from_edges(edges);
+    check_names(&graph, &mut problems);
+    check_cycles(&graph, &mut problems);
+    check_outlets(&graph, &mut problems);
+    check_components(&graph, edges, &mut problems);
+    problems.sort_by_key(|p| p.line);
+    problems
+}
+
+/// Self-loops, duplicate edges and nodes with more than one
+/// downstream node
+fn check_edges(edges: &[Edge], problems: &mut Vec<Problem>) {
+    let mut seen: HashMap<(&str, &str), Option<usize>> = HashMap::new();
+    let mut downstream: HashMap<&str, (&str, Option<usize>)> = HashMap::new();
+    for edge in edges {
+        let Some(ref to) = edge.to else {
+            continue;
+        };
+        if edge.from == *to {
+            problems.push(Problem::new(
+                edge.line,
+                format!("Node `{}` flows into itself", edge.from),
+            ));
+            continue;
+        }
+        if let Some(prev) = seen.insert((&edge.from, to), edge.line) {
+            problems.push(Problem::new(
+                edge.line,
+                format!("Duplicate edge `{} -> {to}`{}", edge.from, on_line(prev)),
+            ));
+            continue;
+        }
+        match downstream.get(edge.from.as_str()) {
+            Some(&(prev, line)) => problems.push(Problem::new(
+                edge.line,
+                format!(
+                    "Node `{}` has more than one downstream node\nit already flows into `{prev}`{}",
+                    edge.from,
+                    on_line(line)
+                ),
+            )),
+            None => {
+                downstream.insert(&edge.from, (to, edge.line));
+            }
+        }
+    }
+}
+
+/// Names that are probably meant to be the same node: they differ
+/// only in case or in `-`/`_`/space, which needs quoting
+fn check_names(graph: &Graph, problems: &mut Vec<Problem>) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (i, name) in graph.names.iter().enumerate() {
+        let key = normalize_name(name);
+        match seen.get(&key) {
+            Some(&prev) => problems.push(Problem::new(
+                graph.lines[i],
+                format!(
+                    "Node `{name}` looks like the node `{}`{}\nthey are different nodes in the network",
+                    graph.names[prev],
+                    on_line(graph.lines[prev])
+                ),
+            )),
+            None => {
+                seen.insert(key, i);
+            }
+        }
+    }
+}
+
+/// Lowercase name with the separators that need quoting replaced by
+/// `_`, e.g. both `"at-dallas"` and `At_Dallas` become `at_dallas`
+pub fn normalize_name(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn check_cycles(graph: &Graph, problems: &mut Vec<Problem>) {
+    // 0: not visited, 1: on the current path, 2: done
+    let mut state = vec![0u8; graph.names.len()];
+    for start in 0..graph.names.len() {
+        let mut path = Vec::new();
+        let mut node = Some(start);
+        while let Some(n) = node {
+            match state[n] {
+                0 => {
+                    state[n] = 1;
+                    path.push(n);
+                    node = graph.output[n];
+                }
+                1 => {
+                    let pos = path.iter().position(|&p| p == n).unwrap_or_default();
+                    let cycle: Vec<&str> = path[pos..]
+                        .iter()
+                        .chain([&n])
+                        .map(|&p| graph.names[p])
+                        .collect();
+                    // point to the edge that closes the cycle
+                    let last = path.last().copied().unwrap_or(n);
+                    problems.push(Problem::new(
+                        graph.output_line[last],
+                        format!("Cycle in the network: {}", cycle.join(" -> ")),
+                    ));
+                    break;
+                }
+                _ => break,
+            }
+        }
+        for n in path {
+            state[n] = 2;
+        }
+    }
+}
+
+/// A river network should drain into a single outlet
+fn check_outlets(graph: &Graph, problems: &mut Vec<Problem>) {
+    let outlets = graph.outlets();
+    let Some((&first, rest)) = outlets.split_first() else {
+        return;
+    };
+    for &n in rest {
+        problems.push(Problem::new(
+            graph.lines[n],
+            format!(
+                "Node `{}` is another outlet (has no downstream node)\nthe first outlet is `{}`{}",
+                graph.names[n],
+                graph.names[first],
+                on_line(graph.lines[first])
+            ),
+        ));
+    }
+}
+
+/// Groups of nodes that are not connected to the largest group
+fn check_components(graph: &Graph, edges: &[Edge], problems: &mut Vec<Problem>) {
+    // undirected neighbours including the extra downstream nodes
+    let mut links = vec![Vec::new(); graph.names.len()];
+    for edge in edges {
+        let Some(ref to) = edge.to else {
+            continue;
+        };
+        if let (Ok(from), Ok(to)) = (graph.node(&edge.from), graph.node(to)) {
+            links[from].push(to);
+            links[to].push(from);
+        }
+    }
+    let mut component = vec![usize::MAX; graph.names.len()];
+    let mut members: Vec<Vec<usize>> = Vec::new();
+    for start in 0..graph.names.len() {
+        if component[start] != usize::MAX {
+            continue;
+        }
+        let id = members.len();
+        let mut nodes = vec![start];
+        component[start] = id;
+        let mut stack = vec![start];
+        while let Some(n) = stack.pop() {
+            for &l in &links[n] {
+                if component[l] == usize::MAX {
+                    component[l] = id;
+                    nodes.push(l);
+                    stack.push(l);
+                }
+            }
+        }
+        nodes.sort();
+        members.push(nodes);
+    }
+    if members.len() < 2 {
+        return;
+    }
+    let largest = (0..members.len())
+        .max_by_key(|&c| (members[c].len(), std::cmp::Reverse(c)))
+        .unwrap_or_default();
+    let groups: BTreeMap<usize, &Vec<usize>> = members
+        .iter()
+        .enumerate()
+        .filter(|(c, _)| *c != largest)
+        .map(|(_, m)| (m[0], m))
+        .collect();
+    for nodes in groups.values() {
+        let names: Vec<&str> = nodes.iter().take(5).map(|&n| graph.names[n]).collect();
+        let more = if nodes.len() > 5 { ", ..." } else { "" };
+        problems.push(Problem::new(
+            graph.lines[nodes[0]],
+            format!(
+                "{} node(s) are not connected to the rest of the network: {}{more}",
+                nodes.len(),
+                names.join(", ")
+            ),
+        ));
+    }
+}
+
+fn on_line(line: Option<usize>) -> String {
+    line.map(|l| format!(" on line {l}")).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::read::parse_connections;
+
+    /// Line and first line of the message of the problems
+    fn problems(txt: &str) -> Vec<(Option<usize>, String)> {
+        let edges = parse_connections(txt).unwrap();
+        find_problems(&edges)
+            .into_iter()
+            .map(|p| (p.line, p.msg.lines().next().unwrap_or_default().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn valid_network() {
+        assert!(problems("a -> b -> d\nc -> d\n").is_empty());
+    }
+
+    #[test]
+    fn cycle() {
+        assert_eq!(
+            problems("a -> b\nb -> c\nc -> b\n"),
+            [(Some(3), "Cycle in the network: b -> c -> b".to_string())]
+        );
+    }
+
+    #[test]
+    fn edges() {
+        assert_eq!(
+            problems("a -> b\nc -> b\na -> b\nc -> a\nb -> b\n"),
+            [
+                (Some(3), "Duplicate edge `a -> b` on line 1".to_string()),
+                (
+                    Some(4),
+                    "Node `c` has more than one downstream node".to_string()
+                ),
+                (Some(5), "Node `b` flows into itself".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn similar_names() {
+        assert_eq!(normalize_name("\"at-dallas\""), "_at_dallas_");
+        assert_eq!(normalize_name("At Dallas"), normalize_name("at_dallas"));
+        assert_eq!(
+            problems("at_dallas -> b\n\"at-dallas\" -> b\n"),
+            [(
+                Some(2),
+                "Node `at-dallas` looks like the node `at_dallas` on line 1".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn disconnected() {
+        assert_eq!(
+            problems("a -> b -> c\nd -> c\n\ne -> f\n"),
+            [
+                (
+                    Some(4),
+                    "Node `f` is another outlet (has no downstream node)".to_string()
+                ),
+                (
+                    Some(4),
+                    "2 node(s) are not connected to the rest of the network: e, f".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Differences between two versions of a network
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use colored::Colorize;
+use serde_json::{json, Value};
+
+use super::Graph;
+use crate::cli::DiffArgs;
+
+struct Diff<'a> {
+    added: Vec<&'a str>,
+    removed: Vec<&'a str>,
+    renamed: Vec<(&'a str, &'a str)>,
+    /// Node (new name), old and new downstream nodes
+    changed: Vec<(&'a str, Option<&'a str>, Option<&'a str>)>,
+}
+
+pub fn diff(args: &DiffArgs) -> anyhow::Result<()> {
+    let old_edges = super::load_edges(&args.old, args.format)?;
+    let new_edges = super::load_edges(&args.new, args.format)?;
+    let old = Graph::from_edges(&old_edges);
+    let new = Graph::from_edges(&new_edges);
+    let diff = compare(&old, &new);
+    if args.json {
+        println!("{:#}", to_json(&diff, &old, &new));
+    } else {
+        print_diff(&diff, &old, &new);
+    }
+    Ok(())
+}
+
+fn compare<'a>(old: &Graph<'a>, new: &Graph<'a>) -> Diff<'a> {
+    let mut removed: Vec<&str> = old
+        .names
+        .iter()
+        .copied()
+        .filter(|n| !new.contains(n))
+        .collect();
+    let mut added: Vec<&str> = new
+        .names
+        .iter()
+        .copied()
+        .filter(|n| !old.contains(n))
+        .collect();
+    let renamed = find_renames(old, new, &removed, &added);
+    removed.retain(|n| !renamed.iter().any(|(o, _)| o == n));
+    added.retain(|n| !renamed.iter().any(|(_, r)| r == n));
+
+    let renames: HashMap<&str, &str> = renamed.iter().copied().collect();
+    let rename = |n: &'a str| renames.get(n).copied().unwrap_or(n);
+    let mut changed = Vec::new();
+    for &name in &old.names {
+        let new_name = rename(name);
+        if !new.contains(new_name) {
+            continue;
+        }
+        let old_out = old.output_name(name).map(rename);
+        let new_out = new.output_name(new_name);
+        if old_out != new_out {
+            changed.push((new_name, old.output_name(name), new_out));
+        }
+    }
+    Diff {
+        added,
+        removed,
+        renamed,
+        changed,
+    }
+}
+
+/// Removed and added nodes that are probably the same node renamed:
+/// their downstream and upstream nodes are the same, after applying
+/// the renames found so far. The removed and added nodes not matched
+/// yet can stand for each other, so that a chain of renamed nodes is
+/// found. Only the unambiguous matches are taken.
+fn find_renames<'a>(
+    old: &Graph<'a>,
+    new: &Graph<'a>,
+    removed: &[&'a str],
+    added: &[&'a str],
+) -> Vec<(&'a str, &'a str)> {
+    let mut renames: HashMap<&str, &str> = HashMap::new();
+    let mut taken: HashSet<&str> = HashSet::new();
+    loop {
+        let rename = |n: &'a str| renames.get(n).copied().unwrap_or(n);
+        let pending_old = |n: &str| removed.contains(&n) && !renames.contains_key(n);
+        let pending_new = |n: &str| added.contains(&n) && !taken.contains(n);
+        // inputs known in both networks, and the number still pending
+        let old_inputs = |n: &str| {
+            let (pending, known): (Vec<&str>, Vec<&str>) =
+                old.input_names(n).partition(|i| pending_old(i));
+            let known: BTreeSet<&str> = known.into_iter().map(rename).collect();
+            (known, pending.len())
+        };
+        let new_inputs = |n: &str| {
+            let (pending, known): (Vec<&str>, Vec<&str>) =
+                new.input_names(n).partition(|i| pending_new(i));
+            (known.into_iter().collect::<BTreeSet<&str>>(), pending.len())
+        };
+        let same_output = |r: &str, a: &str| match (old.output_name(r), new.output_name(a)) {
+            (Some(o), Some(n)) if pending_old(o) => pending_new(n),
+            (o, n) => o.map(rename) == n,
+        };
+        let mut found = Vec::new();
+        for &r in removed.iter().filter(|r| !renames.contains_key(*r)) {
+            let inputs = old_inputs(r);
+            let candidates: Vec<&str> = added
+                .iter()
+                .copied()
+                .filter(|a| !taken.contains(a))
+                .filter(|a| same_output(r, a) && new_inputs(a) == inputs)
+                .collect();
+            if let [a] = candidates[..] {
+                found.push((r, a));
+            }
+        }
+        // two removed nodes matching the same added node is ambiguous
+        let mut count: HashMap<&str, usize> = HashMap::new();
+        for (_, a) in &found {
+            *count.entry(a).or_default() += 1;
+        }
+        found.retain(|(_, a)| count[a] == 1);
+        if found.is_empty() {
+            break;
+        }
+        for (r, a) in found {
+            renames.insert(r, a);
+            taken.insert(a);
+        }
+    }
+    removed
+        .iter()
+        .filter_map(|&r| Some((r, *renames.get(r)?)))
+        .collect()
+}
+
+fn print_diff(diff: &Diff, old: &Graph, new: &Graph) {
+    let down = |out: Option<&str>| out.map(|o| format!(" -> {o}")).unwrap_or_default();
+    for (o, n) in &diff.renamed {
+        println!("{}", format!("~ {o} => {n}").yellow());
+    }
+    for r in &diff.removed {
+        println!("{}", format!("- {r}{}", down(old.output_name(r))).red());
+    }
+    for a in &diff.added {
+        println!("{}", format!("+ {a}{}", down(new.output_name(a))).green());
+    }
+    for (n, o, d) in &diff.changed {
+        println!(
+            "{}",
+            format!(
+                "* {n}: {} => {}",
+                o.unwrap_or("(outlet)"),
+                d.unwrap_or("(outlet)")
+            )
+            .cyan()
+        );
+    }
+    eprintln!(
+        "{} added, {} removed, {} renamed, {} changed downstream",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.renamed.len(),
+        diff.changed.len()
+    );
+}
+
+fn to_json(diff: &Diff, old: &Graph, new: &Graph) -> Value {
+    json!({
+        "added": diff.added.iter().map(|a| json!({
+            "name": a,
+            "downstream": new.output_name(a),
+        })).collect::<Vec<_>>(),
+        "removed": diff.removed.iter().map(|r| json!({
+            "name": r,
+            "downstream": old.output_name(r),
+        })).collect::<Vec<_>>(),
+        "renamed": diff.renamed.iter().map(|(o, n)| json!({
+            "old": o,
+            "new": n,
+        })).collect::<Vec<_>>(),
+        "changed": diff.changed.iter().map(|(n, o, d)| json!({
+            "name": n,
+            "old_downstream": o,
+            "new_downstream": d,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::read::parse_connections;
+
+    /// Compare the networks written as connections files
+    fn check(old: &str, new: &str, test: impl FnOnce(Diff)) {
+        let old_edges = parse_connections(old).unwrap();
+        let new_edges = parse_connections(new).unwrap();
+        test(compare(
+            &Graph::from_edges(&old_edges),
+            &Graph::from_edges(&new_edges),
+        ));
+    }
+
+    #[test]
+    fn rename() {
+        check(
+            "a -> c\nb -> c\nc -> d\n",
+            "a -> c\nx -> c\nc -> d\n",
+            |diff| {
+                assert_eq!(diff.renamed, [("b", "x")]);
+                assert!(diff.added.is_empty());
+                assert!(diff.removed.is_empty());
+                assert!(diff.changed.is_empty());
+            },
+        );
+    }
+
+    #[test]
+    fn chained_renames() {
+        check("a -> b -> c -> d\n", "a -> y -> z -> d\n", |diff| {
+            assert_eq!(diff.renamed, [("b", "y"), ("c", "z")]);
+            assert!(diff.added.is_empty());
+            assert!(diff.removed.is_empty());
+            assert!(diff.changed.is_empty());
+        });
+    }
+
+    #[test]
+    fn ambiguous_renames() {
+        // either of the old headwaters could be either of the new ones
+        check("a -> c\nb -> c\n", "x -> c\ny -> c\n", |diff| {
+            assert!(diff.renamed.is_empty());
+            assert_eq!(diff.removed, ["a", "b"]);
+            assert_eq!(diff.added, ["x", "y"]);
+        });
+    }
+
+    #[test]
+    fn changed_downstream() {
+        check("a -> b -> d\nc -> d\n", "a -> c -> d\nb -> d\n", |diff| {
+            assert!(diff.renamed.is_empty());
+            assert_eq!(diff.changed, [("a", Some("b"), Some("c"))]);
+        });
+        // the downstream of a renamed node is compared by its new name
+        check("a -> b -> d\n", "a -> x -> d\ne -> d\n", |diff| {
+            assert_eq!(diff.renamed, [("b", "x")]);
+            assert_eq!(diff.added, ["e"]);
+            assert!(diff.changed.is_empty());
+        });
+    }
+}
@@ -0,0 +1,217 @@
+//! Writing the network in other file formats
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use clap::ValueEnum;
+use nadi_core::attrs::Attribute;
+use nadi_core::network::Network;
+use serde_json::{json, Map, Value};
+
+use super::{nodes, quote_name, NodeInfo};
+use crate::attrs::{self, value_text};
+use crate::cli::ConvertArgs;
+use crate::table;
+
+/// File formats the network can be written in
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    /// nadi connections file
+    Connections,
+    /// Edge list with `from` and `to` columns
+    Csv,
+    /// Tab separated edge list with `from` and `to` columns
+    Tsv,
+    /// Graphviz DOT, with the node attributes
+    Dot,
+    /// GraphML, with the node attributes
+    Graphml,
+    /// networkx style node-link JSON, with the node attributes
+    Json,
+    /// Mermaid flowchart
+    Mermaid,
+}
+
+pub fn convert(args: &ConvertArgs) -> anyhow::Result<()> {
+    let net = args.input.load()?;
+    let out = export(&net, args.to);
+    match args.output {
+        Some(ref path) => std::fs::write(path, out)?,
+        None => print!("{out}"),
+    }
+    Ok(())
+}
+
+pub fn export(net: &Network, format: ExportFormat) -> String {
+    let nodes = nodes(net);
+    match format {
+        ExportFormat::Connections => connections(&nodes),
+        ExportFormat::Csv => edge_list(&nodes, ','),
+        ExportFormat::Tsv => edge_list(&nodes, '\t'),
+        ExportFormat::Dot => dot(&nodes),
+        ExportFormat::Graphml => graphml(&nodes),
+        ExportFormat::Json => json(&nodes),
+        ExportFormat::Mermaid => mermaid(&nodes),
+    }
+}
+
+fn edges(nodes: &[NodeInfo]) -> impl Iterator<Item = (&str, &str)> {
+    nodes
+        .iter()
+        .filter_map(|n| Some((n.name.as_str(), n.output.as_deref()?)))
+}
+
+pub fn connections(nodes: &[NodeInfo]) -> String {
+    edges(nodes)
+        .map(|(from, to)| format!("{} -> {}\n", quote_name(from), quote_name(to)))
+        .collect()
+}
+
+fn edge_list(nodes: &[NodeInfo], delim: char) -> String {
+    let mut out = format!("from{delim}to\n");
+    for (from, to) in edges(nodes) {
+        let _ = writeln!(
+            out,
+            "{}{delim}{}",
+            table::quote(from, delim),
+            table::quote(to, delim)
+        );
+    }
+    out
+}
+
+fn dot(nodes: &[NodeInfo]) -> String {
+    let mut out = String::from("digraph network {\n");
+    for n in nodes {
+        let attrs: Vec<String> = std::iter::once(format!("INDEX={}", n.index))
+            .chain(
+                n.attrs
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", dot_quote(k), dot_quote(&value_text(v)))),
+            )
+            .collect();
+        let _ = writeln!(out, "  {} [{}];", dot_quote(&n.name), attrs.join(", "));
+    }
+    for (from, to) in edges(nodes) {
+        let _ = writeln!(out, "  {} -> {};", dot_quote(from), dot_quote(to));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// DOT quoted string, `\"` is the only escape sequence in it
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\\\""))
+}
+
+fn graphml(nodes: &[NodeInfo]) -> String {
+    // GraphML needs one type per attribute, mixed types become strings
+    let mut types: BTreeMap<&str, &str> = BTreeMap::new();
+    for n in nodes {
+        for (k, v) in &n.attrs {
+            let ty = match v {
+                Attribute::Bool(_) => "boolean",
+                Attribute::Integer(_) => "long",
+                Attribute::Float(_) => "double",
+                _ => "string",
+            };
+            types
+                .entry(k)
+                .and_modify(|t| {
+                    if *t != ty {
+                        *t = "string"
+                    }
+                })
+                .or_insert(ty);
+        }
+    }
+    let keys: BTreeMap<&str, String> = types
+        .keys()
+        .enumerate()
+        .map(|(i, k)| (*k, format!("d{i}")))
+        .collect();
+
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+        "  <key id=\"index\" for=\"node\" attr.name=\"INDEX\" attr.type=\"long\"/>\n"
+    ));
+    for (name, ty) in &types {
+        let _ = writeln!(
+            out,
+            "  <key id=\"{}\" for=\"node\" attr.name=\"{}\" attr.type=\"{ty}\"/>",
+            keys[name],
+            xml_escape(name)
+        );
+    }
+    out.push_str("  <graph id=\"network\" edgedefault=\"directed\">\n");
+    for n in nodes {
+        let _ = writeln!(out, "    <node id=\"{}\">", xml_escape(&n.name));
+        let _ = writeln!(out, "      <data key=\"index\">{}</data>", n.index);
+        for (k, v) in &n.attrs {
+            let _ = writeln!(
+                out,
+                "      <data key=\"{}\">{}</data>",
+                keys[k.as_str()],
+                xml_escape(&value_text(v))
+            );
+        }
+        out.push_str("    </node>\n");
+    }
+    for (from, to) in edges(nodes) {
+        let _ = writeln!(
+            out,
+            "    <edge source=\"{}\" target=\"{}\"/>",
+            xml_escape(from),
+            xml_escape(to)
+        );
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn json(nodes: &[NodeInfo]) -> String {
+    let json_nodes: Vec<Value> = nodes
+        .iter()
+        .map(|n| {
+            let mut obj = Map::new();
+            obj.insert("id".to_string(), json!(n.name));
+            obj.insert("INDEX".to_string(), json!(n.index));
+            for (k, v) in &n.attrs {
+                obj.insert(k.clone(), attrs::to_json(v));
+            }
+            Value::Object(obj)
+        })
+        .collect();
+    let links: Vec<Value> = edges(nodes)
+        .map(|(from, to)| json!({"source": from, "target": to}))
+        .collect();
+    let doc = json!({
+        "directed": true,
+        "multigraph": false,
+        "graph": {},
+        "nodes": json_nodes,
+        "links": links,
+    });
+    format!("{doc:#}\n")
+}
+
+fn mermaid(nodes: &[NodeInfo]) -> String {
+    let ids: BTreeMap<&str, usize> = nodes.iter().map(|n| (n.name.as_str(), n.index)).collect();
+    let mut out = String::from("flowchart BT\n");
+    for n in nodes {
+        let _ = writeln!(out, "  n{}[\"{}\"]", n.index, n.name.replace('"', "#quot;"));
+    }
+    for (from, to) in edges(nodes) {
+        let _ = writeln!(out, "  n{} --> n{}", ids[from], ids[to]);
+    }
+    out
+}
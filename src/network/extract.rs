@@ -0,0 +1,127 @@
+//! Extracting a part of the network into a new connections file
+
+use std::collections::HashSet;
+
+use super::read::split_names;
+use super::{quote_name, Graph};
+use crate::cli::ExtractArgs;
+
+pub fn extract(args: &ExtractArgs) -> anyhow::Result<()> {
+    let edges = args.input.edges()?;
+    let graph = Graph::from_edges(&edges);
+
+    let selected = if let Some(ref node) = args.upstream_of {
+        upstream(&graph, graph.node(node)?)
+    } else if let Some(ref node) = args.downstream_of {
+        downstream(&graph, graph.node(node)?)
+    } else if let [ref start, ref end] = args.between[..] {
+        path(&graph, start, end)?
+    } else if let Some(ref sel) = args.select {
+        select(&graph, sel)?
+    } else {
+        anyhow::bail!("Nothing to extract, give one of the selections");
+    };
+    let selected: HashSet<&str> = selected.into_iter().map(|n| graph.names[n]).collect();
+
+    let mut out = String::new();
+    let mut count = 0;
+    for edge in &edges {
+        let Some(ref to) = edge.to else {
+            continue;
+        };
+        if selected.contains(edge.from.as_str()) && selected.contains(to.as_str()) {
+            out.push_str(&format!(
+                "{} -> {}\n",
+                quote_name(&edge.from),
+                quote_name(to)
+            ));
+            count += 1;
+        }
+    }
+    if count == 0 {
+        eprintln!(
+            "Warning: selection has {} node(s) and no edges, the connections file will be empty",
+            selected.len()
+        );
+    }
+    match args.output {
+        Some(ref path) => std::fs::write(path, out)?,
+        None => print!("{out}"),
+    }
+    eprintln!("Extracted {} node(s) and {count} edge(s)", selected.len());
+    Ok(())
+}
+
+/// The node and all the nodes upstream of it
+fn upstream(graph: &Graph, node: usize) -> HashSet<usize> {
+    let mut nodes = HashSet::new();
+    let mut stack = vec![node];
+    while let Some(n) = stack.pop() {
+        if nodes.insert(n) {
+            stack.extend(&graph.inputs[n]);
+        }
+    }
+    nodes
+}
+
+/// The node and all the nodes downstream of it till the outlet
+fn downstream(graph: &Graph, node: usize) -> HashSet<usize> {
+    let mut nodes = HashSet::new();
+    let mut node = Some(node);
+    while let Some(n) = node {
+        if !nodes.insert(n) {
+            break;
+        }
+        node = graph.output[n];
+    }
+    nodes
+}
+
+/// Nodes in the path from `start` down to `end`, both included
+fn path(graph: &Graph, start: &str, end: &str) -> anyhow::Result<HashSet<usize>> {
+    let mut nodes = HashSet::new();
+    let mut node = Some(graph.node(start)?);
+    let last = graph.node(end)?;
+    while let Some(n) = node {
+        if !nodes.insert(n) {
+            break;
+        }
+        if n == last {
+            return Ok(nodes);
+        }
+        node = graph.output[n];
+    }
+    anyhow::bail!("Node `{end}` is not downstream of `{start}`")
+}
+
+/// Nodes in the same syntax as `node[...]`: a path `a -> b`, or a
+/// list of names `a, b, c`
+fn select(graph: &Graph, sel: &str) -> anyhow::Result<HashSet<usize>> {
+    let names = split_names(sel).map_err(anyhow::Error::msg)?;
+    match names[..] {
+        [] => anyhow::bail!("Empty selection"),
+        [ref start, ref end] => path(graph, start, end),
+        [_] => split_list(sel).iter().map(|n| graph.node(n)).collect(),
+        _ => anyhow::bail!("Path selection should be `start -> end`, found `{sel}`"),
+    }
+}
+
+/// Comma separated node names, which can be quoted
+fn split_list(list: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut name = String::new();
+    let mut quoted = false;
+    for c in list.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => names.push(std::mem::take(&mut name)),
+            _ => name.push(c),
+        }
+    }
+    names.push(name);
+    names
+        .into_iter()
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .collect()
+}
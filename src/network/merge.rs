@@ -0,0 +1,242 @@
+//! Merging several networks into one, e.g. sub-basins that share the
+//! boundary nodes
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use super::read::Edge;
+use super::NetworkFormat;
+use crate::cli::{MergeArgs, MergeOptions};
+
+/// What to do when a node has different downstream nodes in two of
+/// the networks
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum Collision {
+    /// Report the nodes and stop
+    #[default]
+    Error,
+    /// Rename the node in the later network as `{file stem}_{name}`
+    Prefix,
+}
+
+pub fn merge_cmd(args: &MergeArgs) -> anyhow::Result<()> {
+    let edges = merge(&args.files, args.format, &args.merge)?;
+    let net = super::read::to_network(&edges)?;
+    let out = super::export::export(&net, args.to);
+    match args.output {
+        Some(ref path) => std::fs::write(path, out)?,
+        None => print!("{out}"),
+    }
+    Ok(())
+}
+
+/// Edges of all the networks; a node in more than one network is the
+/// same node as long as it flows into the same node (or is the outlet)
+/// in all but one of them
+pub fn merge(
+    files: &[PathBuf],
+    format: Option<NetworkFormat>,
+    opts: &MergeOptions,
+) -> anyhow::Result<Vec<Edge>> {
+    let networks = files
+        .iter()
+        .map(|file| Ok((file.as_path(), super::load_edges(file, format)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    merge_edges(networks, opts)
+}
+
+/// Merge the edges of the networks, with the files they are from
+fn merge_edges(
+    networks: Vec<(&Path, Vec<Edge>)>,
+    opts: &MergeOptions,
+) -> anyhow::Result<Vec<Edge>> {
+    let mut edges: Vec<Edge> = Vec::new();
+    // downstream node and the file each node was first seen in
+    let mut output: HashMap<String, Option<String>> = HashMap::new();
+    let mut origin: HashMap<String, &Path> = HashMap::new();
+    let mut collisions = Vec::new();
+
+    for (file, mut new_edges) in networks {
+        let mut renames = HashMap::new();
+        for edge in &new_edges {
+            let (Some(Some(prev)), Some(to)) = (output.get(&edge.from), &edge.to) else {
+                continue;
+            };
+            if prev == to || renames.contains_key(&edge.from) {
+                continue;
+            }
+            match opts.on_collision {
+                Collision::Error => collisions.push(format!(
+                    "Node `{}` flows into `{prev}` in {} and into `{to}` in {}",
+                    edge.from,
+                    origin[&edge.from].display(),
+                    file.display()
+                )),
+                Collision::Prefix => {
+                    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+                    let name = format!("{stem}_{}", edge.from);
+                    eprintln!(
+                        "Warning: Node `{}` in {} renamed to `{name}`",
+                        edge.from,
+                        file.display()
+                    );
+                    renames.insert(edge.from.clone(), name);
+                }
+            }
+        }
+        for edge in &mut new_edges {
+            for name in std::iter::once(&mut edge.from).chain(edge.to.as_mut()) {
+                if let Some(new) = renames.get(name) {
+                    *name = new.clone();
+                }
+            }
+        }
+
+        for edge in new_edges {
+            // a node without edges only needs to be added once
+            if edge.to.is_none() && output.contains_key(&edge.from) {
+                continue;
+            }
+            for name in std::iter::once(&edge.from).chain(&edge.to) {
+                origin.entry(name.clone()).or_insert(file);
+                output.entry(name.clone()).or_insert(None);
+            }
+            let out = output.entry(edge.from.clone()).or_default();
+            match (out.as_ref(), &edge.to) {
+                // same edge in both networks
+                (Some(prev), Some(to)) if prev == to => continue,
+                (None, Some(to)) => *out = Some(to.clone()),
+                _ => (),
+            }
+            edges.push(edge);
+        }
+    }
+    if !collisions.is_empty() {
+        anyhow::bail!(
+            "{} node name collision(s), use `--on-collision prefix` to rename them:\n{}",
+            collisions.len(),
+            collisions.join("\n")
+        );
+    }
+
+    for (outlet, node) in &opts.join {
+        match output.get(outlet) {
+            None => anyhow::bail!("Node `{outlet}` to join not found in the networks"),
+            Some(Some(out)) => {
+                anyhow::bail!("Node `{outlet}` to join is not an outlet, it flows into `{out}`")
+            }
+            Some(None) => (),
+        }
+        if !output.contains_key(node) {
+            anyhow::bail!("Node `{node}` to join not found in the networks");
+        }
+        output.insert(outlet.clone(), Some(node.clone()));
+        edges.push(Edge {
+            from: outlet.clone(),
+            to: Some(node.clone()),
+            line: None,
+        });
+    }
+    Ok(edges)
+}
+
+/// Parse the `OUTLET=NODE` value of `--join`
+pub fn parse_join(val: &str) -> Result<(String, String), String> {
+    match val.split_once('=') {
+        Some((outlet, node)) if !outlet.trim().is_empty() && !node.trim().is_empty() => {
+            Ok((outlet.trim().to_string(), node.trim().to_string()))
+        }
+        _ => Err(format!("expected `OUTLET=NODE`, found `{val}`")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::read::parse_connections;
+
+    /// Merge the networks written as connections files
+    fn merge_txt(
+        networks: &[(&str, &str)],
+        on_collision: Collision,
+        join: &[(&str, &str)],
+    ) -> anyhow::Result<Vec<String>> {
+        let networks = networks
+            .iter()
+            .map(|(file, txt)| (Path::new(file), parse_connections(txt).unwrap()))
+            .collect();
+        let opts = MergeOptions {
+            join: join
+                .iter()
+                .map(|(o, n)| (o.to_string(), n.to_string()))
+                .collect(),
+            on_collision,
+        };
+        Ok(merge_edges(networks, &opts)?
+            .into_iter()
+            .map(|e| match e.to {
+                Some(to) => format!("{} -> {to}", e.from),
+                None => e.from,
+            })
+            .collect())
+    }
+
+    #[test]
+    fn shared_nodes() {
+        let edges = merge_txt(
+            &[
+                ("up.txt", "a -> b\nb -> c\n"),
+                ("down.txt", "b -> c\nc -> d\n"),
+            ],
+            Collision::Error,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(edges, ["a -> b", "b -> c", "c -> d"]);
+    }
+
+    #[test]
+    fn collisions() {
+        let networks = [("one.txt", "a -> b\n"), ("two.txt", "a -> c\nc -> d\n")];
+        let err = merge_txt(&networks, Collision::Error, &[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "1 node name collision(s), use `--on-collision prefix` to rename them:\n\
+             Node `a` flows into `b` in one.txt and into `c` in two.txt"
+        );
+        let edges = merge_txt(&networks, Collision::Prefix, &[]).unwrap();
+        assert_eq!(edges, ["a -> b", "two_a -> c", "c -> d"]);
+    }
+
+    #[test]
+    fn join() {
+        let networks = [("one.txt", "a -> b\n"), ("two.txt", "c -> d\n")];
+        let edges = merge_txt(&networks, Collision::Error, &[("b", "c")]).unwrap();
+        assert_eq!(edges, ["a -> b", "c -> d", "b -> c"]);
+
+        let err = merge_txt(&networks, Collision::Error, &[("a", "c")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node `a` to join is not an outlet, it flows into `b`"
+        );
+        let err = merge_txt(&networks, Collision::Error, &[("x", "c")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node `x` to join not found in the networks"
+        );
+        let err = merge_txt(&networks, Collision::Error, &[("b", "x")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node `x` to join not found in the networks"
+        );
+    }
+
+    #[test]
+    fn join_values() {
+        assert_eq!(parse_join(" a = b"), Ok(("a".to_string(), "b".to_string())));
+        assert!(parse_join("a=").is_err());
+        assert!(parse_join("a").is_err());
+    }
+}
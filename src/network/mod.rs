@@ -0,0 +1,303 @@
+//! Reading and inspecting the river networks from the command line,
+//! without writing a tasks file
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use clap::ValueEnum;
+use nadi_core::attrs::{Attribute, HasAttributes};
+use nadi_core::network::Network;
+use nadi_core::node::NodeInner;
+
+use crate::cli::{NetworkArgs, NetworkCommand, NetworkFileArgs};
+
+pub mod check;
+pub mod diff;
+pub mod export;
+pub mod extract;
+pub mod merge;
+pub mod read;
+pub mod stats;
+pub mod tree;
+
+/// File formats the network can be read from
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum NetworkFormat {
+    /// nadi connections file with `a -> b` edges
+    Connections,
+    /// Edge list with `from` and `to` columns
+    Csv,
+    /// Tab separated edge list with `from` and `to` columns
+    Tsv,
+    /// Graphviz DOT file
+    Dot,
+    /// GraphML file
+    Graphml,
+    /// JSON adjacency (`{"a": "b"}` or `{"a": ["b"]}`), or networkx
+    /// style node-link/adjacency data
+    Json,
+}
+
+impl NetworkFormat {
+    /// Format guessed from the file extension
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .as_deref()
+        {
+            Some("csv") => Self::Csv,
+            Some("tsv" | "tab") => Self::Tsv,
+            Some("dot" | "gv") => Self::Dot,
+            Some("graphml") => Self::Graphml,
+            Some("json") => Self::Json,
+            _ => Self::Connections,
+        }
+    }
+}
+
+pub fn run(cmd: NetworkCommand) -> anyhow::Result<()> {
+    match cmd {
+        NetworkCommand::Convert(args) => export::convert(&args),
+        NetworkCommand::Check(args) => check::check(&args),
+        NetworkCommand::Tree(args) => tree::tree(&args),
+        NetworkCommand::Stats(args) => stats::stats(&args),
+        NetworkCommand::Extract(args) => extract::extract(&args),
+        NetworkCommand::Diff(args) => diff::diff(&args),
+        NetworkCommand::Merge(args) => merge::merge_cmd(&args),
+    }
+}
+
+/// Load the network from the file, in the given format or the one
+/// guessed from its extension
+pub fn load(path: &Path, format: Option<NetworkFormat>) -> anyhow::Result<Network> {
+    let format = format.unwrap_or_else(|| NetworkFormat::from_path(path));
+    if format == NetworkFormat::Connections {
+        return Ok(Network::from_file(path)?);
+    }
+    let edges = read::read_edges(path, format)?;
+    read::to_network(&edges)
+}
+
+/// Edges from the file, in the given format or the one guessed from
+/// its extension
+pub fn load_edges(path: &Path, format: Option<NetworkFormat>) -> anyhow::Result<Vec<read::Edge>> {
+    let format = format.unwrap_or_else(|| NetworkFormat::from_path(path));
+    read::read_edges(path, format)
+}
+
+impl NetworkArgs {
+    /// Load the network, merging them if more than one file is given,
+    /// and the node attributes
+    pub fn load(&self) -> anyhow::Result<Option<Network>> {
+        let net = match self.network[..] {
+            [] => return Ok(None),
+            [ref file] if self.merge.join.is_empty() => load(file, self.network_format)?,
+            _ => {
+                let edges = merge::merge(&self.network, self.network_format, &self.merge)?;
+                read::to_network(&edges)?
+            }
+        };
+        if let Some(ref dir) = self.attributes {
+            crate::attrs::load_dir(&net, dir)?;
+        }
+        if let Some(ref table) = self.attr_table {
+            crate::attrs::load_table(&net, table, &self.key)?;
+        }
+        Ok(Some(net))
+    }
+}
+
+impl NetworkFileArgs {
+    pub fn load(&self) -> anyhow::Result<Network> {
+        load(&self.file, self.format)
+    }
+
+    /// Edges as written in the file, without building the network
+    pub fn edges(&self) -> anyhow::Result<Vec<read::Edge>> {
+        load_edges(&self.file, self.format)
+    }
+}
+
+/// Snapshot of a node in the network, so the node locks are not held
+/// while processing them
+pub struct NodeInfo {
+    pub name: String,
+    pub index: usize,
+    pub output: Option<String>,
+    pub attrs: Vec<(String, Attribute)>,
+}
+
+/// Snapshot of all the nodes in the network order
+pub fn nodes(net: &Network) -> Vec<NodeInfo> {
+    net.nodes()
+        .map(|node| {
+            let node = node.lock();
+            NodeInfo {
+                name: node.name().to_string(),
+                index: node.index(),
+                output: output_name(&node),
+                attrs: node_attrs(&node),
+            }
+        })
+        .collect()
+}
+
+/// Nodes of the network as indices with their downstream node and
+/// inputs, built from the edges of a file or from a loaded network
+pub struct Graph<'a> {
+    /// Node names in the order they first appear
+    pub names: Vec<&'a str>,
+    /// Line each node first appears on
+    pub lines: Vec<Option<usize>>,
+    /// Downstream node; only the first one is kept when the file has
+    /// more than one
+    pub output: Vec<Option<usize>>,
+    /// Line of the edge to the downstream node
+    pub output_line: Vec<Option<usize>>,
+    /// Upstream nodes in the order their edges appear
+    pub inputs: Vec<Vec<usize>>,
+    index: HashMap<&'a str, usize>,
+}
+
+impl<'a> Graph<'a> {
+    fn new() -> Self {
+        Self {
+            names: Vec::new(),
+            lines: Vec::new(),
+            output: Vec::new(),
+            output_line: Vec::new(),
+            inputs: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, name: &'a str, line: Option<usize>) -> usize {
+        if let Some(&i) = self.index.get(name) {
+            return i;
+        }
+        self.names.push(name);
+        self.lines.push(line);
+        self.output.push(None);
+        self.output_line.push(None);
+        self.inputs.push(Vec::new());
+        self.index.insert(name, self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    fn connect(&mut self, from: usize, to: usize, line: Option<usize>) {
+        if from != to && self.output[from].is_none() {
+            self.output[from] = Some(to);
+            self.output_line[from] = line;
+            self.inputs[to].push(from);
+        }
+    }
+
+    /// Graph of the edges as written in a file; self-loops and the
+    /// extra downstream nodes are left out
+    pub fn from_edges(edges: &'a [read::Edge]) -> Self {
+        let mut graph = Self::new();
+        for edge in edges {
+            let from = graph.add(&edge.from, edge.line);
+            if let Some(ref to) = edge.to {
+                let to = graph.add(to, edge.line);
+                graph.connect(from, to, edge.line);
+            }
+        }
+        graph
+    }
+
+    /// Graph of the nodes of a network, in the same order
+    pub fn from_nodes(nodes: &'a [NodeInfo]) -> Self {
+        let mut graph = Self::new();
+        for node in nodes {
+            graph.add(&node.name, None);
+        }
+        for (i, node) in nodes.iter().enumerate() {
+            if let Some(&out) = node.output.as_deref().and_then(|o| graph.index.get(o)) {
+                graph.connect(i, out, None);
+            }
+        }
+        graph
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.index.contains_key(name)
+    }
+
+    /// Index of the node with the name
+    pub fn node(&self, name: &str) -> anyhow::Result<usize> {
+        match self.index.get(name) {
+            Some(&i) => Ok(i),
+            None => anyhow::bail!("Node `{name}` not found in the network"),
+        }
+    }
+
+    /// Name of the downstream node of the named node
+    pub fn output_name(&self, name: &str) -> Option<&'a str> {
+        self.output[*self.index.get(name)?].map(|o| self.names[o])
+    }
+
+    /// Names of the inputs of the named node
+    pub fn input_names(&self, name: &str) -> impl Iterator<Item = &'a str> + '_ {
+        self.index
+            .get(name)
+            .into_iter()
+            .flat_map(|&i| self.inputs[i].iter().map(|&n| self.names[n]))
+    }
+
+    /// Nodes without a downstream node
+    pub fn outlets(&self) -> Vec<usize> {
+        (0..self.names.len())
+            .filter(|&i| self.output[i].is_none())
+            .collect()
+    }
+
+    /// Nodes ordered so that every node comes after all of its inputs;
+    /// it keeps its own stack as the networks can be thousands of
+    /// nodes deep. Nodes in a cycle are left out.
+    pub fn upstream_first(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.names.len());
+        for outlet in self.outlets() {
+            let mut stack = vec![(outlet, false)];
+            while let Some((n, done)) = stack.pop() {
+                if done {
+                    order.push(n);
+                } else {
+                    stack.push((n, true));
+                    stack.extend(self.inputs[n].iter().map(|&i| (i, false)));
+                }
+            }
+        }
+        order
+    }
+}
+
+/// Name of the node's downstream node, if any
+pub fn output_name(node: &NodeInner) -> Option<String> {
+    node.output()
+        .map(|out| out.lock().name().to_string())
+        .into()
+}
+
+/// Attributes of the node, sorted by name
+pub fn node_attrs(node: &NodeInner) -> Vec<(String, Attribute)> {
+    let mut attrs: Vec<(String, Attribute)> = node
+        .attr_map()
+        .keys()
+        .filter_map(|k| Some((k.to_string(), node.attr(k)?.clone())))
+        .collect();
+    attrs.sort_by(|a, b| a.0.cmp(&b.0));
+    attrs
+}
+
+/// Node name as written in the connections file and tasks, quoted
+/// unless it is a plain identifier
+pub fn quote_name(name: &str) -> String {
+    if crate::source::is_identifier(name) {
+        name.to_string()
+    } else {
+        format!("{name:?}")
+    }
+}
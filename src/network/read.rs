@@ -0,0 +1,537 @@
+//! Reading the network edges from different file formats
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use nadi_core::network::Network;
+use serde_json::Value;
+
+use super::NetworkFormat;
+use crate::table;
+
+/// Edge from a node to its downstream node
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: String,
+    /// Downstream node, `None` for a node that is in the file without
+    /// any edges (e.g. `{"a": null}` in JSON)
+    pub to: Option<String>,
+    /// Line number (1-based) in the file, if the format has lines
+    pub line: Option<usize>,
+}
+
+impl Edge {
+    fn new(from: &str, to: &str, line: Option<usize>) -> Self {
+        Self {
+            from: from.to_string(),
+            to: Some(to.to_string()),
+            line,
+        }
+    }
+
+    fn node(name: &str, line: Option<usize>) -> Self {
+        Self {
+            from: name.to_string(),
+            to: None,
+            line,
+        }
+    }
+}
+
+/// Add the nodes that are not in any of the edges yet, so the nodes
+/// declared on their own in the file are not lost
+fn add_nodes<'a>(edges: &mut Vec<Edge>, nodes: impl IntoIterator<Item = (&'a str, Option<usize>)>) {
+    let mut seen: HashSet<String> = edges
+        .iter()
+        .flat_map(|e| [Some(&e.from), e.to.as_ref()])
+        .flatten()
+        .cloned()
+        .collect();
+    for (name, line) in nodes {
+        if seen.insert(name.to_string()) {
+            edges.push(Edge::node(name, line));
+        }
+    }
+}
+
+pub fn read_edges(path: &Path, format: NetworkFormat) -> anyhow::Result<Vec<Edge>> {
+    let txt = std::fs::read_to_string(path)?;
+    match format {
+        NetworkFormat::Connections => parse_connections(&txt),
+        NetworkFormat::Csv => parse_table(&txt, ','),
+        NetworkFormat::Tsv => parse_table(&txt, '\t'),
+        NetworkFormat::Dot => Ok(parse_dot(&txt)),
+        NetworkFormat::Graphml => parse_graphml(&txt),
+        NetworkFormat::Json => parse_json(&txt),
+    }
+}
+
+/// Network from the edges; the network is made of the edges, so a
+/// node that is not in any of them is an error instead of being left
+/// out
+pub fn to_network(edges: &[Edge]) -> anyhow::Result<Network> {
+    let pairs: Vec<(&str, &str)> = edges
+        .iter()
+        .filter_map(|e| Some((e.from.as_str(), e.to.as_deref()?)))
+        .collect();
+    let connected: HashSet<&str> = pairs.iter().flat_map(|&(a, b)| [a, b]).collect();
+    if let Some(lone) = edges
+        .iter()
+        .find(|e| e.to.is_none() && !connected.contains(e.from.as_str()))
+    {
+        let at = lone.line.map(|l| format!("Line {l}: ")).unwrap_or_default();
+        anyhow::bail!(
+            "{at}Node `{}` is not connected to any other node",
+            lone.from
+        );
+    }
+    Network::from_edges(&pairs).map_err(anyhow::Error::msg)
+}
+
+/// Connections file with one `a -> b` edge per line; names can be
+/// quoted, and `#` starts a comment
+pub fn parse_connections(txt: &str) -> anyhow::Result<Vec<Edge>> {
+    let mut edges = Vec::new();
+    for (i, line) in txt.lines().enumerate() {
+        let line_no = i + 1;
+        let names =
+            split_names(line).map_err(|e| anyhow::Error::msg(format!("Line {line_no}: {e}")))?;
+        match names.len() {
+            0 => (),
+            1 => anyhow::bail!("Line {line_no}: expected `a -> b`, found `{}`", line.trim()),
+            _ => edges.extend(
+                names
+                    .windows(2)
+                    .map(|w| Edge::new(&w[0], &w[1], Some(line_no))),
+            ),
+        }
+    }
+    Ok(edges)
+}
+
+/// Names separated by `->` in a connections file line
+pub(super) fn split_names(line: &str) -> Result<Vec<String>, String> {
+    let mut names = Vec::new();
+    let mut name = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => name.push(c),
+            '#' => break,
+            '-' if chars.peek() == Some(&'>') => {
+                chars.next();
+                names.push(std::mem::take(&mut name));
+            }
+            _ => name.push(c),
+        }
+    }
+    if quoted {
+        return Err("unclosed quote".to_string());
+    }
+    names.push(name);
+    let names: Vec<String> = names.into_iter().map(|n| n.trim().to_string()).collect();
+    if names.len() == 1 && names[0].is_empty() {
+        return Ok(Vec::new());
+    }
+    if names.iter().any(|n| n.is_empty()) {
+        return Err(format!("empty node name in `{}`", line.trim()));
+    }
+    Ok(names)
+}
+
+/// Edge list table with `from` and `to` columns, or the first two
+/// columns if there are no such headers; an empty `to` is a node
+/// without a downstream node
+fn parse_table(txt: &str, delim: char) -> anyhow::Result<Vec<Edge>> {
+    let table = table::read(txt, delim)?;
+    let (from, to) = match (table.column("from"), table.column("to")) {
+        (Some(f), Some(t)) => (f, t),
+        _ if table.header.len() >= 2 => (0, 1),
+        _ => anyhow::bail!("Edge list needs `from` and `to` columns"),
+    };
+    let mut edges = Vec::new();
+    let mut nodes = Vec::new();
+    for (line, row) in &table.rows {
+        match (row[from].as_str(), row[to].as_str()) {
+            ("", _) => anyhow::bail!("Line {line}: empty node name"),
+            (f, "") => nodes.push((f, Some(*line))),
+            (f, t) => edges.push(Edge::new(f, t, Some(*line))),
+        }
+    }
+    add_nodes(&mut edges, nodes);
+    Ok(edges)
+}
+
+/// Edges (`a -> b -> c` or `a -- b`) and the nodes of the node
+/// statements from a Graphviz DOT file; the attributes and subgraphs
+/// are ignored
+fn parse_dot(txt: &str) -> Vec<Edge> {
+    const KEYWORDS: [&str; 6] = ["strict", "graph", "digraph", "subgraph", "node", "edge"];
+    let mut edges = Vec::new();
+    let mut nodes: Vec<(String, usize)> = Vec::new();
+    // node id, unless `=` after it makes it an attribute name
+    let mut pending: Option<(String, usize)> = None;
+    let mut prev: Option<String> = None;
+    let mut arrow = false;
+    let mut attr_depth = 0;
+    let mut skip_value = false;
+    let mut graph_id = false;
+    for (line, tok) in dot_tokens(txt) {
+        if tok != "=" && attr_depth == 0 {
+            nodes.extend(pending.take());
+        }
+        match tok.as_str() {
+            "[" => attr_depth += 1,
+            "]" => attr_depth -= 1,
+            _ if attr_depth > 0 => (),
+            "->" | "--" => arrow = true,
+            "=" => {
+                skip_value = true;
+                pending = None;
+            }
+            "{" | "}" | ";" | "," => {
+                prev = None;
+                arrow = false;
+                graph_id = false;
+            }
+            _ if skip_value => {
+                skip_value = false;
+                prev = None;
+            }
+            t if KEYWORDS.contains(&t) => {
+                prev = None;
+                graph_id = t.ends_with("graph");
+            }
+            // name of the graph or subgraph
+            _ if graph_id => graph_id = false,
+            _ => {
+                let id = tok
+                    .strip_prefix('"')
+                    .and_then(|t| t.strip_suffix('"'))
+                    .unwrap_or(&tok);
+                if let (Some(p), true) = (&prev, arrow) {
+                    edges.push(Edge::new(p, id, Some(line)));
+                }
+                arrow = false;
+                prev = Some(id.to_string());
+                pending = Some((id.to_string(), line));
+            }
+        }
+    }
+    nodes.extend(pending);
+    add_nodes(
+        &mut edges,
+        nodes.iter().map(|(n, l)| (n.as_str(), Some(*l))),
+    );
+    edges
+}
+
+/// Tokens in a DOT file with their line numbers; quoted strings keep
+/// their quotes so they are not confused with the keywords
+fn dot_tokens(txt: &str) -> Vec<(usize, String)> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = txt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            _ if c.is_whitespace() => (),
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|&c| c != '\n').is_some() {},
+            '#' => while chars.next_if(|&c| c != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'*') => {
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            '"' => {
+                let start = line;
+                let mut s = String::from('"');
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => s.extend(chars.next()),
+                        '"' => break,
+                        '\n' => {
+                            line += 1;
+                            s.push(c);
+                        }
+                        _ => s.push(c),
+                    }
+                }
+                s.push('"');
+                tokens.push((start, s));
+            }
+            '-' if matches!(chars.peek(), Some('>' | '-')) => {
+                let next = chars.next().unwrap_or_default();
+                tokens.push((line, format!("-{next}")));
+            }
+            _ if c.is_alphanumeric() || c == '_' || c == '.' => {
+                let mut s = String::from(c);
+                while let Some(c) = chars.next_if(|&c| c.is_alphanumeric() || c == '_' || c == '.')
+                {
+                    s.push(c);
+                }
+                tokens.push((line, s));
+            }
+            _ => tokens.push((line, c.to_string())),
+        }
+    }
+    tokens
+}
+
+/// Edges from the `<edge source=".." target=".."/>` elements of a
+/// GraphML file, and the `<node id=".."/>` elements without edges
+fn parse_graphml(txt: &str) -> anyhow::Result<Vec<Edge>> {
+    let mut edges = Vec::new();
+    for (line, tag) in xml_tags(txt, "edge") {
+        match (xml_attr(tag, "source"), xml_attr(tag, "target")) {
+            (Some(s), Some(t)) => edges.push(Edge::new(&s, &t, Some(line))),
+            _ => anyhow::bail!("Line {line}: edge without source or target"),
+        }
+    }
+    let mut nodes = Vec::new();
+    for (line, tag) in xml_tags(txt, "node") {
+        match xml_attr(tag, "id") {
+            Some(id) => nodes.push((id, Some(line))),
+            None => anyhow::bail!("Line {line}: node without id"),
+        }
+    }
+    add_nodes(&mut edges, nodes.iter().map(|(n, l)| (n.as_str(), *l)));
+    Ok(edges)
+}
+
+/// Opening tags of the elements with the name, and their line numbers
+fn xml_tags<'a>(txt: &'a str, name: &str) -> Vec<(usize, &'a str)> {
+    let pat = format!("<{name}");
+    txt.match_indices(&pat)
+        .filter_map(|(pos, _)| {
+            let tag = &txt[pos..];
+            let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+            // not an element with a longer name like `<nodes`
+            let after = tag[pat.len()..].chars().next();
+            if !matches!(after, None | Some('/')) && !after?.is_whitespace() {
+                return None;
+            }
+            Some((txt[..pos].matches('\n').count() + 1, tag))
+        })
+        .collect()
+}
+
+/// Value of the attribute in an XML tag, with the basic entities
+/// replaced
+fn xml_attr(tag: &str, name: &str) -> Option<String> {
+    let val = tag.match_indices(name).find_map(|(pos, _)| {
+        if !tag[..pos].ends_with(char::is_whitespace) {
+            return None;
+        }
+        let rest = tag[(pos + name.len())..].trim_start().strip_prefix('=')?;
+        let rest = rest.trim_start();
+        let quote = rest.chars().next().filter(|&q| q == '"' || q == '\'')?;
+        let rest = &rest[1..];
+        Some(&rest[..rest.find(quote)?])
+    })?;
+    Some(
+        val.replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&"),
+    )
+}
+
+/// Edges from a JSON document, which can be a map from each node to
+/// its downstream node(s), or networkx style node-link (`nodes` and
+/// `links`/`edges`) or adjacency (`nodes` and `adjacency`) data
+fn parse_json(txt: &str) -> anyhow::Result<Vec<Edge>> {
+    let doc: Value = serde_json::from_str(txt)?;
+    let Value::Object(obj) = doc else {
+        anyhow::bail!("JSON network should be an object");
+    };
+    let nodes = obj.get("nodes").and_then(Value::as_array);
+    let links = obj
+        .get("links")
+        .or_else(|| obj.get("edges"))
+        .and_then(Value::as_array);
+    let adjacency = obj.get("adjacency").and_then(Value::as_array);
+
+    // networkx uses the `id` for links, but the `name` is nicer to use
+    // in the tasks if the nodes have one
+    let names: HashMap<String, String> = nodes
+        .into_iter()
+        .flatten()
+        .filter_map(|n| {
+            let id = json_name(n.get("id")?)?;
+            let name = n
+                .get("name")
+                .and_then(json_name)
+                .unwrap_or_else(|| id.clone());
+            Some((id, name))
+        })
+        .collect();
+    let name = |v: &Value| -> anyhow::Result<String> {
+        let id = json_name(v).ok_or_else(|| anyhow::Error::msg(format!("Invalid node id {v}")))?;
+        Ok(names.get(&id).cloned().unwrap_or(id))
+    };
+
+    let mut edges = Vec::new();
+    if let Some(links) = links {
+        for link in links {
+            let (Some(s), Some(t)) = (link.get("source"), link.get("target")) else {
+                anyhow::bail!("Link without source or target: {link}");
+            };
+            edges.push(Edge::new(&name(s)?, &name(t)?, None));
+        }
+        let all: Vec<String> = names.values().cloned().collect();
+        add_nodes(&mut edges, all.iter().map(|n| (n.as_str(), None)));
+    } else if let (Some(nodes), Some(adjacency)) = (nodes, adjacency) {
+        let mut all = Vec::new();
+        for (node, adj) in nodes.iter().zip(adjacency) {
+            let from = name(node.get("id").unwrap_or(&Value::Null))?;
+            for to in adj.as_array().into_iter().flatten() {
+                edges.push(Edge::new(&from, &name(to.get("id").unwrap_or(to))?, None));
+            }
+            all.push(from);
+        }
+        add_nodes(&mut edges, all.iter().map(|n| (n.as_str(), None)));
+    } else {
+        let mut outlets = Vec::new();
+        for (from, to) in &obj {
+            match to {
+                Value::Null => outlets.push(from.as_str()),
+                Value::Array(tos) if tos.is_empty() => outlets.push(from.as_str()),
+                Value::Array(tos) => {
+                    for t in tos {
+                        edges.push(Edge::new(from, &name(t)?, None));
+                    }
+                }
+                t => edges.push(Edge::new(from, &name(t)?, None)),
+            }
+        }
+        add_nodes(&mut edges, outlets.into_iter().map(|n| (n, None)));
+    }
+    Ok(edges)
+}
+
+fn json_name(v: &Value) -> Option<String> {
+    match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Edges as `a -> b`, or `a` for the nodes without edges
+    fn pairs(edges: &[Edge]) -> Vec<String> {
+        edges
+            .iter()
+            .map(|e| match e.to {
+                Some(ref to) => format!("{} -> {to}", e.from),
+                None => e.from.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn connections() {
+        let edges = parse_connections("a -> b # comment\n\n\"c d\" -> b -> e\n").unwrap();
+        assert_eq!(pairs(&edges), ["a -> b", "c d -> b", "b -> e"]);
+        assert_eq!(edges[1].line, Some(3));
+        assert!(parse_connections("a -> b\nc\n").is_err());
+        assert!(parse_connections("a -> \"b\n").is_err());
+        assert!(parse_connections("a -> -> b\n").is_err());
+    }
+
+    #[test]
+    fn table() {
+        let edges = parse_table("id,To,From\n1,b,a\n2,,c\n3,c,b\n", ',').unwrap();
+        assert_eq!(pairs(&edges), ["a -> b", "b -> c"]);
+        let edges = parse_table("up\tdown\na\tb\nb\t\n", '\t').unwrap();
+        assert_eq!(pairs(&edges), ["a -> b"]);
+        let edges = parse_table("from,to\na,\n", ',').unwrap();
+        assert_eq!(pairs(&edges), ["a"]);
+        assert_eq!(edges[0].line, Some(2));
+        assert!(parse_table("from,to\n,b\n", ',').is_err());
+    }
+
+    #[test]
+    fn dot() {
+        let txt = r#"strict digraph G {
+    rankdir = LR; // comment
+    node [shape=box];
+    a -> b -> "c \"x\"" [label="a -> z"];
+    subgraph cluster { d; e [color=red] }
+    /* f -> g */
+    e -- b
+}"#;
+        let edges = parse_dot(txt);
+        assert_eq!(pairs(&edges), ["a -> b", "b -> c \"x\"", "e -> b", "d"]);
+        assert_eq!(edges[1].line, Some(4));
+        assert_eq!(edges[3].line, Some(5));
+    }
+
+    #[test]
+    fn graphml() {
+        let txt = "<graphml>\n<graph edgedefault=\"directed\">\n\
+            <node id=\"a\"/><node id=\"b\"/>\n<node\tid = 'c &amp; d'/>\n<nodes/>\n\
+            <edge\tsource = \"a\" target='b'/>\n</graph>\n</graphml>\n";
+        let edges = parse_graphml(txt).unwrap();
+        assert_eq!(pairs(&edges), ["a -> b", "c & d"]);
+        assert_eq!(edges[0].line, Some(6));
+        assert_eq!(edges[1].line, Some(4));
+        assert!(parse_graphml("<edge source=\"a\"/>").is_err());
+    }
+
+    #[test]
+    fn xml_attributes() {
+        assert_eq!(
+            xml_attr("<edge source=\"a\"", "source").as_deref(),
+            Some("a")
+        );
+        assert_eq!(
+            xml_attr("<edge\nsource\t=\t'a'", "source").as_deref(),
+            Some("a")
+        );
+        assert_eq!(
+            xml_attr("<edge datasource=\"x\" source=\"a\"", "source").as_deref(),
+            Some("a")
+        );
+        assert_eq!(xml_attr("<edge target=\"a\"", "source"), None);
+    }
+
+    #[test]
+    fn json_adjacency_map() {
+        let edges = parse_json(r#"{"a": "b", "b": null, "c": ["b"], "d": null, "e": []}"#).unwrap();
+        let mut found = pairs(&edges);
+        found.sort();
+        assert_eq!(found, ["a -> b", "c -> b", "d", "e"]);
+    }
+
+    #[test]
+    fn json_node_link() {
+        let txt = r#"{"nodes": [{"id": 1, "name": "a"}, {"id": 2, "name": "b"}, {"id": 3}],
+                      "links": [{"source": 1, "target": 2}]}"#;
+        let edges = parse_json(txt).unwrap();
+        assert_eq!(pairs(&edges), ["a -> b", "3"]);
+        assert!(parse_json(r#"{"nodes": [], "links": [{"source": 1}]}"#).is_err());
+    }
+
+    #[test]
+    fn json_networkx_adjacency() {
+        let txt = r#"{"nodes": [{"id": "a"}, {"id": "b"}, {"id": "c"}],
+                      "adjacency": [[{"id": "b"}], [], []]}"#;
+        let edges = parse_json(txt).unwrap();
+        assert_eq!(pairs(&edges), ["a -> b", "c"]);
+    }
+}
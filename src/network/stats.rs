@@ -0,0 +1,321 @@
+//! Summary statistics of the network topology
+
+use std::collections::BTreeMap;
+
+use colored::Colorize;
+use serde_json::{json, Value};
+
+use super::{nodes, Graph};
+use crate::cli::StatsArgs;
+
+/// Widest bar drawn for the histograms
+const BAR_WIDTH: usize = 40;
+
+/// Network as indices, with the nodes ordered so that every node
+/// comes after all of its upstream nodes
+struct Topology<'a> {
+    graph: Graph<'a>,
+    order: Vec<usize>,
+}
+
+impl<'a> Topology<'a> {
+    fn new(graph: Graph<'a>) -> Self {
+        let order = graph.upstream_first();
+        Self { graph, order }
+    }
+
+    /// Strahler order: 1 for the headwaters, and one more than the
+    /// largest order of the inputs when two or more inputs have it
+    fn strahler(&self) -> Vec<usize> {
+        let mut order = vec![1; self.graph.names.len()];
+        for &n in &self.order {
+            let max = self.graph.inputs[n].iter().map(|&i| order[i]).max();
+            if let Some(max) = max {
+                let count = self.graph.inputs[n]
+                    .iter()
+                    .filter(|&&i| order[i] == max)
+                    .count();
+                order[n] = if count > 1 { max + 1 } else { max };
+            }
+        }
+        order
+    }
+
+    /// Shreve magnitude: 1 for the headwaters, and the sum of the
+    /// inputs for the others
+    fn shreve(&self) -> Vec<usize> {
+        let mut mag = vec![1; self.graph.names.len()];
+        for &n in &self.order {
+            if !self.graph.inputs[n].is_empty() {
+                mag[n] = self.graph.inputs[n].iter().map(|&i| mag[i]).sum();
+            }
+        }
+        mag
+    }
+
+    /// Number of edges in the longest path from a headwater to each
+    /// node, and the input that path comes from
+    fn longest_paths(&self) -> (Vec<usize>, Vec<Option<usize>>) {
+        let mut length = vec![0; self.graph.names.len()];
+        let mut from = vec![None; self.graph.names.len()];
+        for &n in &self.order {
+            if let Some(&i) = self.graph.inputs[n].iter().max_by_key(|&&i| length[i]) {
+                length[n] = length[i] + 1;
+                from[n] = Some(i);
+            }
+        }
+        (length, from)
+    }
+
+    /// Main stem from the outlet up: follows the input with the
+    /// highest Strahler order, the longest path if they are the same
+    fn main_stem(&self, outlet: usize, strahler: &[usize], length: &[usize]) -> Vec<usize> {
+        let mut stem = vec![outlet];
+        let mut node = outlet;
+        while let Some(&up) = self.graph.inputs[node]
+            .iter()
+            .max_by_key(|&&i| (strahler[i], length[i]))
+        {
+            stem.push(up);
+            node = up;
+        }
+        stem
+    }
+}
+
+pub fn stats(args: &StatsArgs) -> anyhow::Result<()> {
+    let net = args.input.load()?;
+    let nodes = nodes(&net);
+    let topo = Topology::new(Graph::from_nodes(&nodes));
+
+    let outlets = topo.graph.outlets();
+    let headwaters = topo.graph.inputs.iter().filter(|i| i.is_empty()).count();
+    let strahler = topo.strahler();
+    let shreve = topo.shreve();
+    let (length, from) = topo.longest_paths();
+    let longest = outlets.iter().copied().max_by_key(|&o| length[o]);
+    let longest_path = longest.map(|mut node| {
+        let mut path = vec![node];
+        while let Some(up) = from[node] {
+            path.push(up);
+            node = up;
+        }
+        path
+    });
+    let main_stem = outlets
+        .iter()
+        .map(|&o| topo.main_stem(o, &strahler, &length))
+        .max_by_key(|s| s.len())
+        .unwrap_or_default();
+
+    let tributaries = histogram(topo.graph.inputs.iter().map(|i| i.len()));
+    let strahler_hist = histogram(strahler.iter().copied());
+    let shreve_hist = binned(&shreve);
+
+    let ends = |path: &[usize]| -> (String, String) {
+        match (path.last(), path.first()) {
+            (Some(&h), Some(&o)) => (nodes[h].name.clone(), nodes[o].name.clone()),
+            _ => Default::default(),
+        }
+    };
+    let longest_path = longest_path.unwrap_or_default();
+    let (path_from, path_to) = ends(&longest_path);
+    let (stem_from, stem_to) = ends(&main_stem);
+
+    if args.json {
+        let hist = |h: &[(String, usize)]| -> Value {
+            Value::Object(h.iter().map(|(k, v)| (k.clone(), json!(v))).collect())
+        };
+        let doc = json!({
+            "nodes": nodes.len(),
+            "edges": topo.graph.output.iter().filter(|o| o.is_some()).count(),
+            "outlets": outlets.len(),
+            "headwaters": headwaters,
+            "max_path_length": {
+                "length": longest_path.len().saturating_sub(1),
+                "from": path_from,
+                "to": path_to,
+            },
+            "main_stem": {
+                "nodes": main_stem.len(),
+                "from": stem_from,
+                "to": stem_to,
+            },
+            "tributaries": hist(&tributaries),
+            "strahler": hist(&strahler_hist),
+            "shreve": hist(&shreve_hist),
+        });
+        println!("{doc:#}");
+        return Ok(());
+    }
+
+    let row = |name: &str, val: String| println!("{} {val}", format!("{name:<18}").bold());
+    row("Nodes", nodes.len().to_string());
+    row(
+        "Edges",
+        topo.graph
+            .output
+            .iter()
+            .filter(|o| o.is_some())
+            .count()
+            .to_string(),
+    );
+    row("Outlets", outlets.len().to_string());
+    row("Headwaters", headwaters.to_string());
+    row(
+        "Max path length",
+        format!(
+            "{} ({path_from} -> {path_to})",
+            longest_path.len().saturating_sub(1)
+        ),
+    );
+    row(
+        "Main stem",
+        format!("{} nodes ({stem_from} -> {stem_to})", main_stem.len()),
+    );
+    print_histogram("Tributaries per node", &tributaries);
+    print_histogram("Strahler order", &strahler_hist);
+    print_histogram("Shreve magnitude", &shreve_hist);
+    Ok(())
+}
+
+/// Number of nodes with each value
+fn histogram(values: impl Iterator<Item = usize>) -> Vec<(String, usize)> {
+    let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+    for v in values {
+        *counts.entry(v).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect()
+}
+
+/// Number of nodes in bins of powers of two (`1`, `2-3`, `4-7`, ...),
+/// as the magnitudes go up to the number of headwaters
+fn binned(values: &[usize]) -> Vec<(String, usize)> {
+    let mut counts: BTreeMap<u32, usize> = BTreeMap::new();
+    for &v in values {
+        *counts.entry(v.max(1).ilog2()).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(bin, count)| {
+            let (lo, hi) = (1usize << bin, (1usize << (bin + 1)) - 1);
+            let label = if lo == hi {
+                lo.to_string()
+            } else {
+                format!("{lo}-{hi}")
+            };
+            (label, count)
+        })
+        .collect()
+}
+
+fn print_histogram(title: &str, hist: &[(String, usize)]) {
+    println!("\n{}", title.bold());
+    let label_width = hist.iter().map(|(k, _)| k.len()).max().unwrap_or_default();
+    let count_width = hist
+        .iter()
+        .map(|(_, v)| v.to_string().len())
+        .max()
+        .unwrap_or_default();
+    let max = hist.iter().map(|(_, v)| *v).max().unwrap_or(1);
+    for (label, count) in hist {
+        let bar = "#".repeat((count * BAR_WIDTH).div_ceil(max));
+        println!(
+            "  {label:>label_width$}  {count:>count_width$}  {}",
+            bar.cyan()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::read::{parse_connections, Edge};
+
+    fn edges() -> Vec<Edge> {
+        parse_connections("h -> a -> c -> e\nb -> c\nk -> g -> f -> d -> e\n").unwrap()
+    }
+
+    /// Value of each node with its name
+    fn named<'a, T: Copy>(topo: &Topology<'a>, values: &[T]) -> Vec<(&'a str, T)> {
+        topo.graph
+            .names
+            .iter()
+            .copied()
+            .zip(values.iter().copied())
+            .collect()
+    }
+
+    #[test]
+    fn stream_orders() {
+        let edges = edges();
+        let topo = Topology::new(Graph::from_edges(&edges));
+        assert_eq!(
+            named(&topo, &topo.strahler()),
+            [
+                ("h", 1),
+                ("a", 1),
+                ("c", 2),
+                ("e", 2),
+                ("b", 1),
+                ("k", 1),
+                ("g", 1),
+                ("f", 1),
+                ("d", 1),
+            ]
+        );
+        assert_eq!(
+            named(&topo, &topo.shreve()),
+            [
+                ("h", 1),
+                ("a", 1),
+                ("c", 2),
+                ("e", 3),
+                ("b", 1),
+                ("k", 1),
+                ("g", 1),
+                ("f", 1),
+                ("d", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let edges = edges();
+        let topo = Topology::new(Graph::from_edges(&edges));
+        let (length, from) = topo.longest_paths();
+        assert_eq!(
+            named(&topo, &length),
+            [
+                ("h", 0),
+                ("a", 1),
+                ("c", 2),
+                ("e", 4),
+                ("b", 0),
+                ("k", 0),
+                ("g", 1),
+                ("f", 2),
+                ("d", 3),
+            ]
+        );
+        let from_name =
+            |name: &str| from[topo.graph.node(name).unwrap()].map(|n| topo.graph.names[n]);
+        assert_eq!(from_name("e"), Some("d"));
+        assert_eq!(from_name("c"), Some("a"));
+        assert_eq!(from_name("k"), None);
+
+        // the higher Strahler order wins over the longer path, and the
+        // longer path between the inputs of the same order
+        let e = topo.graph.node("e").unwrap();
+        let stem: Vec<&str> = topo
+            .main_stem(e, &topo.strahler(), &length)
+            .into_iter()
+            .map(|n| topo.graph.names[n])
+            .collect();
+        assert_eq!(stem, ["e", "c", "a", "h"]);
+    }
+}
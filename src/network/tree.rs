@@ -0,0 +1,157 @@
+//! Drawing the network as a tree from the outlet up, like `cargo tree`
+
+use colored::Colorize;
+
+use super::{nodes, Graph, NodeInfo};
+use crate::cli::TreeArgs;
+
+/// Characters used to draw the branches
+struct Charset {
+    down: &'static str,
+    branch: &'static str,
+    last: &'static str,
+}
+
+const UTF8: Charset = Charset {
+    down: "│   ",
+    branch: "├── ",
+    last: "└── ",
+};
+
+const ASCII: Charset = Charset {
+    down: "|   ",
+    branch: "|-- ",
+    last: "`-- ",
+};
+
+/// A line of the tree: the branches before the node, and the node
+struct Row<'a> {
+    prefix: String,
+    node: &'a NodeInfo,
+}
+
+pub fn tree(args: &TreeArgs) -> anyhow::Result<()> {
+    let net = args.input.load()?;
+    let nodes = nodes(&net);
+    let graph = Graph::from_nodes(&nodes);
+    let roots = match args.from {
+        Some(ref name) => vec![graph.node(name)?],
+        None => graph.outlets(),
+    };
+
+    let charset = if args.ascii { &ASCII } else { &UTF8 };
+    let mut rows = Vec::new();
+    for root in roots {
+        rows.push(Row {
+            prefix: String::new(),
+            node: &nodes[root],
+        });
+        add_inputs(root, args.depth, &nodes, &graph, charset, &mut rows);
+    }
+    print_rows(&rows, &args.attrs);
+    Ok(())
+}
+
+/// Rows for the inputs of the node, depth first; it keeps its own
+/// stack as a long river can be deeper than the call stack allows
+fn add_inputs<'a>(
+    root: usize,
+    max_depth: Option<usize>,
+    nodes: &'a [NodeInfo],
+    graph: &Graph,
+    charset: &Charset,
+    rows: &mut Vec<Row<'a>>,
+) {
+    // node, its prefix, the prefix of its inputs and its depth
+    let mut stack: Vec<(usize, String, String, usize)> = Vec::new();
+    let push_children = |stack: &mut Vec<_>, node: usize, prefix: &str, depth: usize| {
+        if max_depth.is_some_and(|d| depth > d) {
+            return;
+        }
+        let children = &graph.inputs[node];
+        // pushed in reverse so the first input is drawn first
+        for (i, &child) in children.iter().enumerate().rev() {
+            let last = i + 1 == children.len();
+            stack.push((
+                child,
+                format!(
+                    "{prefix}{}",
+                    if last { charset.last } else { charset.branch }
+                ),
+                format!("{prefix}{}", if last { "    " } else { charset.down }),
+                depth,
+            ));
+        }
+    };
+    push_children(&mut stack, root, "", 1);
+    while let Some((node, prefix, child_prefix, depth)) = stack.pop() {
+        rows.push(Row {
+            prefix,
+            node: &nodes[node],
+        });
+        push_children(&mut stack, node, &child_prefix, depth + 1);
+    }
+}
+
+/// Print the tree with the attribute columns aligned after it
+fn print_rows(rows: &[Row], attrs: &[String]) {
+    let values: Vec<Vec<String>> = rows
+        .iter()
+        .map(|r| attrs.iter().map(|a| column(r.node, a)).collect())
+        .collect();
+    let tree_width = rows
+        .iter()
+        .map(|r| r.prefix.chars().count() + r.node.name.chars().count())
+        .max()
+        .unwrap_or_default();
+    let widths: Vec<usize> = attrs
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            values
+                .iter()
+                .map(|v| v[i].chars().count())
+                .chain([a.len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    if !attrs.is_empty() {
+        let mut header = format!("{:tree_width$}", "");
+        for (a, w) in attrs.iter().zip(&widths) {
+            header.push_str(&format!("  {a:<w$}"));
+        }
+        println!("{}", header.trim_end().bold());
+    }
+    for (row, vals) in rows.iter().zip(&values) {
+        let pad = tree_width - row.prefix.chars().count() - row.node.name.chars().count();
+        let mut line = format!(
+            "{}{}",
+            row.prefix.as_str().dimmed(),
+            row.node.name.as_str().green().bold()
+        );
+        if !attrs.is_empty() {
+            line.push_str(&" ".repeat(pad));
+        }
+        for (v, w) in vals.iter().zip(&widths) {
+            line.push_str(&format!("  {}", format!("{v:<w$}").cyan()));
+        }
+        println!("{}", line.trim_end());
+    }
+}
+
+/// Value of the column for the node; `NAME` and `INDEX` are the node
+/// name and index, others are the node attributes
+fn column(node: &NodeInfo, name: &str) -> String {
+    match name {
+        "NAME" => node.name.clone(),
+        "INDEX" => node.index.to_string(),
+        _ => node
+            .attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.to_string())
+            .unwrap_or_else(|| "-".to_string()),
+    }
+}
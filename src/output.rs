@@ -0,0 +1,55 @@
+//! Machine readable output of the task results
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::literal;
+use crate::source::SourceError;
+
+/// A task that was run (or failed to parse), for `--output json`
+pub struct TaskRecord<'a> {
+    /// Index of the task, 0 for the values set before the run (`-D`
+    /// and the script parameters)
+    pub index: usize,
+    pub file: &'a str,
+    pub line: usize,
+    pub col: usize,
+    /// Source text of the task
+    pub task: &'a str,
+    /// Time taken to run the task, `None` if it was not run
+    pub duration: Option<Duration>,
+    pub result: &'a Result<Option<String>, SourceError>,
+}
+
+impl TaskRecord<'_> {
+    pub fn to_json(&self) -> Value {
+        let mut obj = json!({
+            "index": self.index,
+            "file": self.file,
+            "line": self.line,
+            "col": self.col,
+            "task": self.task,
+            "duration": self.duration.map(|d| d.as_secs_f64()),
+        });
+        match self.result {
+            Ok(res) => {
+                obj["status"] = json!("ok");
+                obj["result"] = res
+                    .as_deref()
+                    .map(literal::result_to_json)
+                    .unwrap_or(Value::Null);
+            }
+            Err(e) => {
+                obj["status"] = json!("error");
+                obj["error"] = json!({
+                    "message": e.msg,
+                    "file": self.file,
+                    "line": e.line,
+                    "col": e.col,
+                });
+            }
+        }
+        obj
+    }
+}
@@ -0,0 +1,146 @@
+//! Timing of the tasks for `--profile`
+
+use std::cmp::Reverse;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+/// Number of slowest nodes shown for each node task in the report
+const TOP_NODES: usize = 5;
+
+/// Node name, start time and duration
+pub type NodeTiming = (String, Duration, Duration);
+
+pub struct TaskTiming {
+    pub location: String,
+    pub task: String,
+    /// Time since the start of profiling when the task started
+    pub start: Duration,
+    pub duration: Duration,
+    /// Start and duration for each node, if the task was run node by node
+    pub nodes: Vec<NodeTiming>,
+}
+
+pub struct Profiler {
+    start: Instant,
+    tasks: Vec<TaskTiming>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Time since the profiling started
+    pub fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn record(&mut self, timing: TaskTiming) {
+        self.tasks.push(timing);
+    }
+
+    /// Print the tasks sorted by the time taken, slowest first
+    pub fn print_report(&self) {
+        let total: Duration = self.tasks.iter().map(|t| t.duration).sum();
+        let mut tasks: Vec<&TaskTiming> = self.tasks.iter().collect();
+        tasks.sort_by_key(|t| Reverse(t.duration));
+        let loc_width = tasks
+            .iter()
+            .map(|t| t.location.len())
+            .max()
+            .unwrap_or_default()
+            .max("Location".len());
+
+        eprintln!(
+            "\n** Profile: {} Task(s) in {:.3}s **",
+            tasks.len(),
+            total.as_secs_f64()
+        );
+        eprintln!(
+            "{:>10}  {:>6}  {:<loc_width$}  Task",
+            "Time (s)", "%", "Location"
+        );
+        for t in tasks {
+            eprintln!(
+                "{:>10.3}  {:>6.1}  {:<loc_width$}  {}",
+                t.duration.as_secs_f64(),
+                percent(t.duration, total),
+                t.location,
+                t.task
+            );
+            let mut nodes: Vec<&NodeTiming> = t.nodes.iter().collect();
+            nodes.sort_by_key(|n| Reverse(n.2));
+            for (name, _, dur) in nodes.iter().take(TOP_NODES) {
+                eprintln!(
+                    "{:>10.3}  {:>6.1}  {:<loc_width$}    node {name}",
+                    dur.as_secs_f64(),
+                    percent(*dur, total),
+                    ""
+                );
+            }
+            if nodes.len() > TOP_NODES {
+                eprintln!(
+                    "{:>10}  {:>6}  {:<loc_width$}    ... {} more nodes",
+                    "",
+                    "",
+                    "",
+                    nodes.len() - TOP_NODES
+                );
+            }
+        }
+    }
+
+    /// Write the timings in the Chrome trace event format, that can
+    /// be opened in `chrome://tracing` or <https://ui.perfetto.dev>
+    pub fn write_trace(&self, path: &Path) -> anyhow::Result<()> {
+        let mut events = Vec::new();
+        for t in &self.tasks {
+            events.push(trace_event(
+                &t.task,
+                "task",
+                t.start,
+                t.duration,
+                &t.location,
+            ));
+            for (name, start, dur) in &t.nodes {
+                events.push(trace_event(name, "node", *start, *dur, &t.location));
+            }
+        }
+        let trace = json!({ "traceEvents": events, "displayTimeUnit": "ms" });
+        std::fs::write(path, trace.to_string())?;
+        Ok(())
+    }
+}
+
+/// Complete ("X") event, times are in microseconds
+fn trace_event(name: &str, cat: &str, start: Duration, dur: Duration, location: &str) -> Value {
+    json!({
+        "name": name,
+        "cat": cat,
+        "ph": "X",
+        "ts": start.as_micros() as u64,
+        "dur": dur.as_micros() as u64,
+        "pid": 1,
+        "tid": 1,
+        "args": { "location": location },
+    })
+}
+
+fn percent(part: Duration, total: Duration) -> f64 {
+    if total.is_zero() {
+        0.0
+    } else {
+        100.0 * part.as_secs_f64() / total.as_secs_f64()
+    }
+}
@@ -0,0 +1,89 @@
+//! Interactive shell that runs tasks as they are typed, keeping the
+//! same [`Session`] (and so the same `TaskContext`) until it exits.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use nadi_core::network::Network;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::session::Session;
+use crate::source;
+
+const PROMPT: &str = "nadi> ";
+const CONTINUATION_PROMPT: &str = "  ... ";
+const HISTORY_FILE: &str = ".nadi_history";
+
+/// Run the interactive shell until EOF (`Ctrl-D`)
+pub fn run(net: Option<Network>) -> anyhow::Result<()> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = interrupted.clone();
+    // While reading a line the terminal is in raw mode and `Ctrl-C`
+    // reaches rustyline as a key press. While a task runs, this
+    // handler keeps the session alive: the running task can not be
+    // stopped from here, so it finishes (and its result is shown)
+    // and the rest of the input is skipped; a second `Ctrl-C` exits.
+    // Child processes the task started are in the terminal's process
+    // group and get the SIGINT themselves.
+    ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!("** Stopping after this task, Ctrl-C again to exit **");
+    })?;
+
+    let mut rl = DefaultEditor::new()?;
+    let history = history_file();
+    if let Some(ref hist) = history {
+        // there is no history file on the first run
+        let _ = rl.load_history(hist);
+    }
+
+    let mut session = Session::new(net, false);
+    session.interactive(interrupted.clone());
+    let mut buffer = String::new();
+    loop {
+        let prompt = if buffer.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        match rl.readline(prompt) {
+            Ok(line) => {
+                buffer.push_str(&line);
+                buffer.push('\n');
+                if source::is_incomplete(&buffer) {
+                    continue;
+                }
+                let input = std::mem::take(&mut buffer);
+                if input.trim().is_empty() {
+                    continue;
+                }
+                rl.add_history_entry(input.trim_end())?;
+                interrupted.store(false, Ordering::SeqCst);
+                // the errors are shown by the session, this is only
+                // for the ones that stop the input, like include cycles
+                if let Err(e) = session.run("<repl>", &input) {
+                    eprintln!("Error: {e}");
+                }
+                if interrupted.swap(false, Ordering::SeqCst) {
+                    eprintln!("** Interrupted **");
+                }
+            }
+            // discard the partially typed input and start over
+            Err(ReadlineError::Interrupted) => buffer.clear(),
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if let Some(ref hist) = history {
+        rl.save_history(hist)?;
+    }
+    Ok(())
+}
+
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
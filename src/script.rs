@@ -0,0 +1,384 @@
+//! Tasks files run as scripts, with named parameters declared at the
+//! top of the file:
+//!
+//! ```text
+//! #!/usr/bin/env nadi
+//! # @param basin: string = "ohio" -- Basin to run the analysis for
+//! # @param year: integer -- Year of the data
+//! # @param wet: bool = false
+//! ```
+//!
+//! `./analysis.tasks --basin ohio --year 2020` then runs the tasks
+//! with `env.basin`, `env.year` and `env.wet` set. The `nadi run`
+//! options can be given along with them (`-n net.txt`), and anything
+//! after `--` is for the parameters.
+
+use std::path::{Path, PathBuf};
+
+use clap::CommandFactory;
+
+use crate::cli::{CliArgs, Command};
+use crate::define;
+use crate::source::is_identifier;
+
+/// Prefix of the parameter declaration in a comment
+const PARAM: &str = "@param";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParamType {
+    String,
+    Integer,
+    Float,
+    Bool,
+    Date,
+    /// Any literal value: arrays, tables...
+    Any,
+}
+
+impl ParamType {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "string" | "str" => Self::String,
+            "integer" | "int" => Self::Integer,
+            "float" => Self::Float,
+            "bool" => Self::Bool,
+            "date" => Self::Date,
+            "any" => Self::Any,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Integer => "integer",
+            Self::Float => "float",
+            Self::Bool => "bool",
+            Self::Date => "date",
+            Self::Any => "any",
+        }
+    }
+
+    /// Value as it is written in the tasks, if it is of this type;
+    /// strings can be given without the quotes
+    fn literal(self, val: &str) -> Option<String> {
+        let val = val.trim();
+        let valid = match self {
+            Self::String if define::is_string(val) => true,
+            Self::String => return Some(format!("{val:?}")),
+            Self::Integer => val.parse::<i64>().is_ok(),
+            Self::Float => val.parse::<f64>().is_ok(),
+            Self::Bool => matches!(val, "true" | "false"),
+            Self::Date => val.parse::<nadi_core::attrs::Date>().is_ok(),
+            Self::Any => define::is_literal(val),
+        };
+        valid.then(|| val.to_string())
+    }
+}
+
+/// Parameter declared in the tasks file
+#[derive(Debug)]
+pub struct Param {
+    name: String,
+    ty: ParamType,
+    /// Default value as written in the tasks, required if `None`
+    default: Option<String>,
+    help: String,
+}
+
+/// Command to run the tasks file when nadi is run as its interpreter
+/// (`nadi FILE ARGS...` from a `#!/usr/bin/env nadi` line); the
+/// arguments after the file are the `run` options, and the values of
+/// its parameters: the `--NAME` flags that `run` does not have, and
+/// anything after `--`
+pub fn command_from_args() -> Option<Command> {
+    let mut args = std::env::args_os().skip(1);
+    let file = args.next()?;
+    let name = file.to_str()?;
+    let cli = CliArgs::command();
+    if name.starts_with('-') || cli.find_subcommand(name).is_some() {
+        return None;
+    }
+    let txt = std::fs::read_to_string(&file).ok()?;
+    if !txt.starts_with("#!") {
+        return None;
+    }
+    let options: Vec<&str> = cli
+        .find_subcommand("run")?
+        .get_arguments()
+        .filter_map(|a| a.get_long())
+        .collect();
+    let rest: Vec<String> = args.map(|a| a.to_string_lossy().to_string()).collect();
+    let (run, values) = split_args(&rest, &options, &params(&txt).unwrap_or_default());
+    let mut argv = vec!["nadi".into(), "run".into(), file];
+    argv.extend(run.into_iter().map(Into::into));
+    if !values.is_empty() {
+        argv.push("--".into());
+        argv.extend(values.into_iter().map(Into::into));
+    }
+    CliArgs::parse_from(argv).command
+}
+
+/// Split the arguments of a script into the `run` options and the
+/// parameter values
+fn split_args(args: &[String], options: &[&str], params: &[Param]) -> (Vec<String>, Vec<String>) {
+    let mut run = Vec::new();
+    let mut values = Vec::new();
+    let mut args = args.iter().peekable();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            values.extend(args.cloned());
+            break;
+        }
+        // `--help` lists the parameters of the script
+        let flag = match arg.strip_prefix("--") {
+            Some(flag) => flag.split('=').next().unwrap_or(flag),
+            None if arg == "-h" => "help",
+            None => {
+                run.push(arg.clone());
+                continue;
+            }
+        };
+        if flag != "help" && options.contains(&flag) {
+            run.push(arg.clone());
+            continue;
+        }
+        values.push(arg.clone());
+        if arg.contains('=') || flag == "help" {
+            continue;
+        }
+        // `--wet` alone is `--wet true`, like in param_tasks
+        let name = flag.replace('-', "_");
+        let is_bool = params
+            .iter()
+            .any(|p| p.name == name && p.ty == ParamType::Bool);
+        let value = args.next_if(|a| {
+            if is_bool {
+                matches!(a.as_str(), "true" | "false")
+            } else {
+                !a.starts_with("--")
+            }
+        });
+        values.extend(value.cloned());
+    }
+    (run, values)
+}
+
+/// Parameters declared in the comments at the top of the tasks, before
+/// the first task
+pub fn params(txt: &str) -> anyhow::Result<Vec<Param>> {
+    let mut params: Vec<Param> = Vec::new();
+    for (i, line) in txt.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some(comment) = line.strip_prefix('#') else {
+            break;
+        };
+        let Some(decl) = comment.trim().strip_prefix(PARAM) else {
+            continue;
+        };
+        let param =
+            parse_param(decl).map_err(|e| anyhow::Error::msg(format!("Line {}: {e}", i + 1)))?;
+        if params.iter().any(|p| p.name == param.name) {
+            anyhow::bail!("Line {}: parameter `{}` declared twice", i + 1, param.name);
+        }
+        params.push(param);
+    }
+    Ok(params)
+}
+
+/// Parameters of all the tasks files, a parameter declared in more
+/// than one of them is taken from the first
+pub fn files_params(files: &[PathBuf]) -> anyhow::Result<Vec<Param>> {
+    let mut params: Vec<Param> = Vec::new();
+    for file in files {
+        let txt = std::fs::read_to_string(file)
+            .map_err(|e| anyhow::Error::msg(format!("Cannot read {}: {e}", file.display())))?;
+        let file_params = self::params(&txt)
+            .map_err(|e| anyhow::Error::msg(format!("{}: {e}", file.display())))?;
+        for param in file_params {
+            if !params.iter().any(|p| p.name == param.name) {
+                params.push(param);
+            }
+        }
+    }
+    Ok(params)
+}
+
+/// `NAME: TYPE [= DEFAULT] [-- HELP]`
+fn parse_param(decl: &str) -> Result<Param, String> {
+    let (decl, help) = split_help(decl);
+    let (name, rest) = decl
+        .split_once(':')
+        .ok_or("expected `@param NAME: TYPE = DEFAULT -- HELP`")?;
+    let name = name.trim();
+    if !is_identifier(name) {
+        return Err(format!("invalid parameter name `{name}`"));
+    }
+    let (ty, default) = match rest.split_once('=') {
+        Some((ty, default)) => (ty.trim(), Some(default.trim())),
+        None => (rest.trim(), None),
+    };
+    let ty = ParamType::from_name(ty).ok_or_else(|| {
+        format!("unknown type `{ty}`, use string, integer, float, bool, date or any")
+    })?;
+    let default = match default {
+        Some(val) => Some(
+            ty.literal(val)
+                .ok_or_else(|| format!("default `{val}` is not a valid {}", ty.name()))?,
+        ),
+        None => None,
+    };
+    Ok(Param {
+        name: name.to_string(),
+        ty,
+        default,
+        help: help.to_string(),
+    })
+}
+
+/// Split the declaration at the `--` before the help text, that is
+/// not inside a string
+fn split_help(decl: &str) -> (&str, &str) {
+    let mut quoted = false;
+    for (i, c) in decl.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '-' if !quoted && decl[i..].starts_with("--") => {
+                return (&decl[..i], decl[i + 2..].trim());
+            }
+            _ => (),
+        }
+    }
+    (decl, "")
+}
+
+/// Tasks setting `env.NAME` for each parameter from the arguments, or
+/// the default value
+pub fn param_tasks(file: &Path, params: &[Param], args: &[String]) -> anyhow::Result<String> {
+    let mut values: Vec<Option<String>> = params.iter().map(|p| p.default.clone()).collect();
+    let mut args = args.iter().peekable();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            anyhow::bail!("Unexpected argument `{arg}`, parameters are given as `--NAME VALUE`");
+        };
+        let (flag, inline) = match flag.split_once('=') {
+            Some((f, v)) => (f, Some(v.to_string())),
+            None => (flag, None),
+        };
+        let name = flag.replace('-', "_");
+        let Some(i) = params.iter().position(|p| p.name == name) else {
+            anyhow::bail!(
+                "Unknown parameter `--{flag}` for {}, see `--help`",
+                file.display()
+            );
+        };
+        let param = &params[i];
+        let val = match inline {
+            Some(v) => v,
+            // `--wet` alone is `--wet true`
+            None if param.ty == ParamType::Bool
+                && !matches!(args.peek(), Some(a) if !a.starts_with("--")) =>
+            {
+                "true".to_string()
+            }
+            None => match args.next() {
+                Some(v) => v.clone(),
+                None => anyhow::bail!("Parameter `--{flag}` needs a value"),
+            },
+        };
+        let Some(lit) = param.ty.literal(&val) else {
+            anyhow::bail!(
+                "Invalid value `{val}` for `--{flag}`, expected {}",
+                param.ty.name()
+            );
+        };
+        values[i] = Some(lit);
+    }
+
+    let mut tasks = String::new();
+    for (param, val) in params.iter().zip(values) {
+        match val {
+            Some(v) => tasks.push_str(&format!("env.{} = {v}\n", param.name)),
+            None => anyhow::bail!(
+                "Missing required parameter `--{}` for {}",
+                param.name,
+                file.display()
+            ),
+        }
+    }
+    Ok(tasks)
+}
+
+/// Help for the parameters of the tasks file, for `--help`
+pub fn usage(file: &Path, params: &[Param]) -> String {
+    let flags: Vec<String> = params
+        .iter()
+        .map(|p| match p.default {
+            Some(_) => format!("[--{} <{}>]", p.name, p.ty.name()),
+            None => format!("--{} <{}>", p.name, p.ty.name()),
+        })
+        .collect();
+    let mut out = format!(
+        "Usage: {} [RUN OPTIONS] {}\n",
+        file.display(),
+        flags.join(" ")
+    );
+    if params.is_empty() {
+        return out;
+    }
+    let args: Vec<String> = params
+        .iter()
+        .map(|p| format!("--{} <{}>", p.name, p.ty.name()))
+        .collect();
+    let width = args.iter().map(|a| a.len()).max().unwrap_or_default();
+    out.push_str("\nParameters:\n");
+    for (p, arg) in params.iter().zip(&args) {
+        let extra = match p.default {
+            Some(ref d) => format!("[default: {d}]"),
+            None => "(required)".to_string(),
+        };
+        let help = if p.help.is_empty() {
+            extra
+        } else {
+            format!("{} {extra}", p.help)
+        };
+        out.push_str(&format!("  {arg:<width$}  {help}\n"));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_arguments() {
+        let params =
+            params("#!/usr/bin/env nadi\n# @param year: int\n# @param wet: bool = false\n")
+                .unwrap();
+        let args: Vec<String> = [
+            "-n",
+            "net.txt",
+            "--year",
+            "2020",
+            "--wet",
+            "--keep-going",
+            "--output=json",
+            "--",
+            "--extra",
+        ]
+        .map(String::from)
+        .to_vec();
+        let (run, values) = split_args(&args, &["network", "keep-going", "output"], &params);
+        assert_eq!(run, ["-n", "net.txt", "--keep-going", "--output=json"]);
+        assert_eq!(values, ["--year", "2020", "--wet", "--extra"]);
+
+        let args = ["--wet", "true", "-h"].map(String::from);
+        let (run, values) = split_args(&args, &["network"], &params);
+        assert!(run.is_empty());
+        assert_eq!(values, ["--wet", "true", "-h"]);
+    }
+}
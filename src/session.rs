@@ -0,0 +1,563 @@
+//! A single run of nadi, where all the tasks sources share the same
+//! [`TaskContext`] and network, unless it is reset for a fresh start.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use nadi_core::attrs::AttrMap;
+use nadi_core::network::Network;
+use nadi_core::tasks::{Task, TaskContext};
+use serde_json::Value;
+
+use crate::cli::OutputFormat;
+use crate::diagnostic::Diagnostic;
+use crate::output::TaskRecord;
+use crate::profile::{NodeTiming, Profiler, TaskTiming};
+use crate::source;
+
+/// A task failed while running in `--keep-going` mode
+pub struct TaskError {
+    pub index: usize,
+    pub location: String,
+    pub task: String,
+    pub msg: String,
+}
+
+/// Parsed task with where it came from
+struct SourceTask<'a> {
+    stmt: source::Statement<'a>,
+    task: Task,
+    /// Whether it is the only task in the statement
+    whole: bool,
+}
+
+/// How a tasks file given to the run went, for the summary
+struct FileRun {
+    file: String,
+    tasks: usize,
+    failed: usize,
+    duration: Duration,
+    /// Whether the run stopped in this file
+    stopped: bool,
+}
+
+/// A task, a file included by an `include "FILE"` directive, or a
+/// statement that could not be parsed (with the error)
+enum Step<'a> {
+    Task(SourceTask<'a>),
+    Include(source::Statement<'a>, PathBuf),
+    Error(source::Statement<'a>, source::SourceError),
+}
+
+pub struct Session {
+    ctx: TaskContext,
+    print_tasks: bool,
+    keep_going: bool,
+    max_errors: Option<usize>,
+    output: OutputFormat,
+    index: usize,
+    errors: Vec<TaskError>,
+    records: Vec<Value>,
+    profiler: Option<Profiler>,
+    trace: bool,
+    per_node: bool,
+    /// Tasks files being run, to find the include cycles
+    files: source::OpenFiles,
+    runs: Vec<FileRun>,
+    /// Set by `Ctrl-C` in the REPL, no more tasks are started once set
+    interrupted: Option<Arc<AtomicBool>>,
+}
+
+impl Session {
+    pub fn new(net: Option<Network>, print_tasks: bool) -> Self {
+        Self {
+            ctx: TaskContext::new(net),
+            print_tasks,
+            keep_going: false,
+            max_errors: None,
+            output: OutputFormat::Text,
+            index: 0,
+            errors: Vec::new(),
+            records: Vec::new(),
+            profiler: None,
+            trace: false,
+            per_node: false,
+            files: source::OpenFiles::default(),
+            runs: Vec::new(),
+            interrupted: None,
+        }
+    }
+
+    /// Record the failed tasks and keep running the others, stop
+    /// after `max_errors` failures if given
+    pub fn keep_going(&mut self, max_errors: Option<usize>) {
+        self.keep_going = true;
+        self.max_errors = max_errors;
+    }
+
+    /// Settings for the REPL: keep going after the errors, do not
+    /// print the banners, and start no more tasks once `interrupted`
+    /// is set
+    pub fn interactive(&mut self, interrupted: Arc<AtomicBool>) {
+        self.keep_going = true;
+        self.interrupted = Some(interrupted);
+    }
+
+    pub fn output(&mut self, format: OutputFormat) {
+        self.output = format;
+    }
+
+    /// Time each task
+    pub fn profile(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    /// Print each task to stderr as it starts
+    pub fn trace(&mut self) {
+        self.trace = true;
+    }
+
+    /// Run the node tasks on all the nodes one node at a time, so
+    /// that each node is traced and timed
+    pub fn per_node(&mut self) {
+        self.per_node = true;
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn errors(&self) -> &[TaskError] {
+        &self.errors
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.ctx.network
+    }
+
+    /// Start over with a new context and network, the errors, output
+    /// and timings of the earlier tasks are kept for the end
+    pub fn reset(&mut self, net: Option<Network>) {
+        self.ctx = TaskContext::new(net);
+    }
+
+    /// Parse and run the tasks in `txt`, any `env`, network or node
+    /// attributes they set are visible to the later calls. `name` is
+    /// used to show where the tasks came from.
+    pub fn run(&mut self, name: &str, txt: &str) -> anyhow::Result<()> {
+        // included files are relative to the file including them
+        let base = self.files.base().to_path_buf();
+        let mut steps = Vec::new();
+        for stmt in source::statements(txt) {
+            let parsed = match stmt.include() {
+                Some(Ok(file)) => {
+                    steps.push(Step::Include(stmt, base.join(file)));
+                    continue;
+                }
+                Some(Err(msg)) => Err(stmt.error(msg)),
+                None => stmt.parse(),
+            };
+            match parsed {
+                Ok(t) => {
+                    let whole = t.len() == 1;
+                    steps.extend(
+                        t.into_iter()
+                            .map(|task| Step::Task(SourceTask { stmt, task, whole })),
+                    );
+                }
+                Err(err) => steps.push(Step::Error(stmt, err)),
+            }
+        }
+        // without `--keep-going` nothing in the source runs if any of
+        // it does not parse, otherwise the errors are reported in order
+        if !self.keep_going {
+            let mut tasks = 0;
+            for step in &steps {
+                match step {
+                    Step::Task(_) => tasks += 1,
+                    Step::Include(..) => (),
+                    // the index it would have had when run; reporting
+                    // it stops the run
+                    Step::Error(stmt, err) => {
+                        let index = self.index + tasks + 1;
+                        self.report(name, txt, index, stmt, None, Err(err.clone()))?;
+                    }
+                }
+            }
+        }
+        if self.print_tasks {
+            for step in &steps {
+                let text = match step {
+                    Step::Task(fc) => fc.task.to_colored_string(),
+                    Step::Include(stmt, _) => stmt.text.to_string(),
+                    Step::Error(..) => continue,
+                };
+                // stdout is only for the records in the JSON outputs
+                if self.output == OutputFormat::Text {
+                    println!("{text}");
+                } else {
+                    eprintln!("{text}");
+                }
+            }
+        }
+
+        if self.output == OutputFormat::Text && self.interrupted.is_none() {
+            let count = steps.iter().filter(|s| matches!(s, Step::Task(_))).count();
+            eprintln!("** Running {count} Script **");
+        }
+        for step in steps {
+            if self
+                .interrupted
+                .as_ref()
+                .is_some_and(|i| i.load(Ordering::SeqCst))
+            {
+                break;
+            }
+            let fc = match step {
+                Step::Task(fc) => fc,
+                Step::Include(stmt, path) => {
+                    self.include(name, txt, &stmt, &path)?;
+                    continue;
+                }
+                Step::Error(stmt, err) => {
+                    self.index += 1;
+                    self.report(name, txt, self.index, &stmt, None, Err(err))?;
+                    continue;
+                }
+            };
+            // numbered as they run, so the tasks of the included files
+            // come between the tasks around the `include`
+            self.index += 1;
+            let index = self.index;
+            if self.trace {
+                eprintln!(
+                    "{} [{}] {name}:{}: {}",
+                    "+".bold(),
+                    index,
+                    fc.stmt.line,
+                    fc.task.to_colored_string()
+                );
+            }
+            // split before the timer starts, so parsing the per node
+            // tasks is not in the timings
+            let node_tasks = if self.per_node && fc.whole {
+                self.per_node_tasks(&fc.stmt)
+            } else {
+                None
+            };
+            let start = Instant::now();
+            let (result, nodes) = match node_tasks {
+                Some(node_tasks) => self.execute_per_node(node_tasks),
+                None => (self.ctx.execute(fc.task), Vec::new()),
+            };
+            let duration = start.elapsed();
+            let result = result.map_err(|e| fc.stmt.error(e));
+            if let Some(ref mut prof) = self.profiler {
+                prof.record(TaskTiming {
+                    location: format!("{name}:{}:{}", fc.stmt.line, fc.stmt.col),
+                    task: fc.stmt.text.lines().next().unwrap_or_default().to_string(),
+                    start: prof.now() - duration,
+                    duration,
+                    nodes,
+                });
+            }
+            self.report(name, txt, index, &fc.stmt, Some(duration), result)?;
+        }
+        Ok(())
+    }
+
+    /// Run the tasks file; the files it includes are relative to it
+    pub fn run_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let (full, txt) = self.files.open(path).map_err(anyhow::Error::msg)?;
+        let (index, errors) = (self.index, self.errors.len());
+        let start = Instant::now();
+        let result = self.run_nested(full, path, &txt);
+        self.runs.push(FileRun {
+            file: path.to_string_lossy().to_string(),
+            tasks: self.index - index,
+            failed: self.errors.len() - errors,
+            duration: start.elapsed(),
+            stopped: result.is_err(),
+        });
+        result
+    }
+
+    /// Run a prelude file like [`run_file`](Self::run_file), but it is
+    /// left out of the per-file summary
+    pub fn run_prelude(&mut self, path: &Path) -> anyhow::Result<()> {
+        let (full, txt) = self.files.open(path).map_err(anyhow::Error::msg)?;
+        self.run_nested(full, path, &txt)
+    }
+
+    fn run_nested(&mut self, full: PathBuf, path: &Path, txt: &str) -> anyhow::Result<()> {
+        self.files.push(full, path);
+        let result = self.run(&path.to_string_lossy(), txt);
+        self.files.pop();
+        result
+    }
+
+    /// Run the file of an `include` directive, the problems with
+    /// reading the file are reported at the directive
+    fn include(
+        &mut self,
+        name: &str,
+        txt: &str,
+        stmt: &source::Statement,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        match self.files.open(path) {
+            Ok((full, included)) => self.run_nested(full, path, &included),
+            Err(msg) => {
+                self.index += 1;
+                self.report(name, txt, self.index, stmt, None, Err(stmt.error(msg)))
+            }
+        }
+    }
+
+    /// Run the tasks that set the values (`-D` and the parameters)
+    /// before the other tasks; only their errors are shown, as a
+    /// record with index 0 in the JSON outputs, and any error stops
+    /// the run
+    pub fn define(&mut self, name: &str, txt: &str) -> anyhow::Result<()> {
+        for stmt in source::statements(txt) {
+            let result = stmt.parse().and_then(|tasks| {
+                tasks
+                    .into_iter()
+                    .try_for_each(|task| self.ctx.execute(task).map(|_| ()))
+                    .map_err(|e| stmt.error(e))
+            });
+            if let Err(err) = result {
+                if self.output == OutputFormat::Text {
+                    eprint!(
+                        "{}",
+                        Diagnostic::new(&err.msg, name, txt, err.line, err.col)
+                    );
+                } else {
+                    self.record(0, name, &stmt, None, &Err(err));
+                }
+                anyhow::bail!("Could not set the value at {name}:{}", stmt.line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the `env` values read from the env file
+    pub fn set_env(&mut self, values: &AttrMap) {
+        for key in values.keys() {
+            if let Some(val) = values.get(key) {
+                self.ctx.env.insert(key.clone(), val.clone());
+            }
+        }
+    }
+
+    /// With `--per-node`, a node function task on all the nodes is
+    /// split into one `node[NAME]` task per node, in the order of the
+    /// nodes in the network (the order nadi runs them in), so that
+    /// each node can be timed and shown. Only a statement that is a
+    /// single task is split.
+    fn per_node_tasks(&self, stmt: &source::Statement) -> Option<Vec<(String, Vec<Task>)>> {
+        let rest = stmt.text.strip_prefix("node")?;
+        if !rest.starts_with(|c: char| c.is_whitespace() || c == '.') {
+            return None;
+        }
+        if stmt.function_call()?.ty != source::CallType::Node {
+            return None;
+        }
+        let node_tasks: Option<Vec<_>> = self
+            .ctx
+            .network
+            .nodes()
+            .map(|node| {
+                let name = node.lock().name().to_string();
+                let task = format!("node[{}]{rest}", crate::network::quote_name(&name));
+                let tokens = nadi_core::parser::tokenizer::get_tokens(&task).ok()?;
+                let tasks = nadi_core::parser::tasks::parse(tokens).ok()?;
+                Some((name, tasks))
+            })
+            .collect();
+        if node_tasks.is_none() {
+            eprintln!(
+                "Warning: Could not run the task at line {} one node at a time, it runs as a whole",
+                stmt.line
+            );
+        }
+        node_tasks
+    }
+
+    fn execute_per_node(
+        &mut self,
+        node_tasks: Vec<(String, Vec<Task>)>,
+    ) -> (Result<Option<String>, String>, Vec<NodeTiming>) {
+        let mut outputs = Vec::new();
+        let mut timings = Vec::new();
+        let since = self.profiler.as_ref().map(|p| p.now()).unwrap_or_default();
+        let start = Instant::now();
+        for (name, tasks) in node_tasks {
+            if self.trace {
+                eprintln!("{} node {name}", "++".bold());
+            }
+            let node_start = start.elapsed();
+            for task in tasks {
+                match self.ctx.execute(task) {
+                    Ok(Some(out)) => outputs.push(out),
+                    Ok(None) => (),
+                    Err(e) => return (Err(e), timings),
+                }
+            }
+            timings.push((name, since + node_start, start.elapsed() - node_start));
+        }
+        let output = (!outputs.is_empty()).then(|| outputs.join("\n"));
+        (Ok(output), timings)
+    }
+
+    /// Show the result of a task in the chosen output format
+    fn report(
+        &mut self,
+        name: &str,
+        txt: &str,
+        index: usize,
+        stmt: &source::Statement,
+        duration: Option<Duration>,
+        result: Result<Option<String>, source::SourceError>,
+    ) -> anyhow::Result<()> {
+        if self.output != OutputFormat::Text {
+            self.record(index, name, stmt, duration, &result);
+        }
+        match result {
+            Ok(Some(p)) if self.output == OutputFormat::Text => println!("{p}"),
+            Ok(_) => (),
+            Err(err) => {
+                if self.output == OutputFormat::Text {
+                    eprint!(
+                        "{}",
+                        Diagnostic::new(&err.msg, name, txt, err.line, err.col)
+                    );
+                }
+                self.fail(TaskError {
+                    index,
+                    location: format!("{name}:{}:{}", stmt.line, stmt.col),
+                    task: stmt.text.lines().next().unwrap_or_default().to_string(),
+                    msg: err.msg,
+                })?
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the record, or keep it for the JSON array at the end
+    fn record(
+        &mut self,
+        index: usize,
+        name: &str,
+        stmt: &source::Statement,
+        duration: Option<Duration>,
+        result: &Result<Option<String>, source::SourceError>,
+    ) {
+        let record = TaskRecord {
+            index,
+            file: name,
+            line: stmt.line,
+            col: stmt.col,
+            task: stmt.text,
+            duration,
+            result,
+        }
+        .to_json();
+        if self.output == OutputFormat::Jsonl {
+            println!("{record}");
+        } else {
+            self.records.push(record);
+        }
+    }
+
+    /// Record the error in `--keep-going` mode, otherwise stop here;
+    /// the error itself should already be shown to the user
+    fn fail(&mut self, err: TaskError) -> anyhow::Result<()> {
+        if !self.keep_going {
+            anyhow::bail!("Task {} at {} failed", err.index, err.location);
+        }
+        self.errors.push(err);
+        match self.max_errors {
+            Some(max) if self.errors.len() >= max => {
+                anyhow::bail!("Stopping after {max} error(s)")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Print what is left at the end of the run: the tables of the
+    /// files run and the failed tasks, or the JSON array of all the
+    /// tasks
+    pub fn finish(&mut self) {
+        match self.output {
+            OutputFormat::Text => self.print_summary(),
+            OutputFormat::Json => {
+                let records = std::mem::take(&mut self.records);
+                println!("{}", Value::Array(records));
+            }
+            OutputFormat::Jsonl => (),
+        }
+    }
+
+    fn print_summary(&self) {
+        if self.runs.len() > 1 {
+            self.print_files();
+        }
+        if self.errors.is_empty() {
+            return;
+        }
+        let loc_width = self
+            .errors
+            .iter()
+            .map(|e| e.location.len())
+            .max()
+            .unwrap_or_default()
+            .max("Location".len());
+        eprintln!("\n** {} Task(s) Failed **", self.errors.len());
+        eprintln!("{:>5}  {:<loc_width$}  Task", "Index", "Location");
+        for err in &self.errors {
+            eprintln!(
+                "{:>5}  {:<loc_width$}  {}",
+                err.index, err.location, err.task
+            );
+            for line in err.msg.lines() {
+                eprintln!("{:>5}  {:<loc_width$}    {line}", "", "");
+            }
+        }
+    }
+
+    /// Table of the tasks files run, when there are more than one
+    fn print_files(&self) {
+        let file_width = self
+            .runs
+            .iter()
+            .map(|r| r.file.len())
+            .max()
+            .unwrap_or_default()
+            .max("File".len());
+        let tasks: usize = self.runs.iter().map(|r| r.tasks).sum();
+        let total: Duration = self.runs.iter().map(|r| r.duration).sum();
+        eprintln!(
+            "\n** {} Files, {tasks} Task(s) in {:.3}s **",
+            self.runs.len(),
+            total.as_secs_f64()
+        );
+        eprintln!(
+            "{:<file_width$}  {:>5}  {:>6}  {:>8}",
+            "File", "Tasks", "Failed", "Time (s)"
+        );
+        for run in &self.runs {
+            let status = if run.stopped { "  stopped" } else { "" };
+            eprintln!(
+                "{:<file_width$}  {:>5}  {:>6}  {:>8.3}{status}",
+                run.file,
+                run.tasks,
+                run.failed,
+                run.duration.as_secs_f64()
+            );
+        }
+    }
+}
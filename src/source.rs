@@ -0,0 +1,600 @@
+//! Helpers for working with raw tasks source text before it is
+//! handed to the tokenizer.
+
+use std::path::{Path, PathBuf};
+
+use nadi_core::parser::{NadiError, ParseError};
+use nadi_core::tasks::Task;
+
+/// State of a source text after scanning it for brackets and strings
+#[derive(Default, Debug, Clone, Copy)]
+struct ScanState {
+    depth: i32,
+    in_string: bool,
+    in_comment: bool,
+    escaped: bool,
+}
+
+impl ScanState {
+    fn feed(&mut self, c: char) {
+        if self.in_comment {
+            if c == '\n' {
+                self.in_comment = false;
+            }
+            return;
+        }
+        if self.in_string {
+            match c {
+                _ if self.escaped => self.escaped = false,
+                '\\' => self.escaped = true,
+                '"' => self.in_string = false,
+                _ => (),
+            }
+            return;
+        }
+        match c {
+            '#' => self.in_comment = true,
+            '"' => self.in_string = true,
+            '(' | '[' | '{' => self.depth += 1,
+            ')' | ']' | '}' => self.depth -= 1,
+            _ => (),
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.in_string || self.depth > 0
+    }
+}
+
+/// Whether the text has unclosed brackets or strings, and more input
+/// is needed before it can be parsed as complete tasks
+pub fn is_incomplete(txt: &str) -> bool {
+    let mut state = ScanState::default();
+    txt.chars().for_each(|c| state.feed(c));
+    state.is_open()
+}
+
+/// Whether the name is a plain identifier in the tasks, that needs no
+/// quotes as a node name and can be used as an attribute name
+pub fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Tasks files being read, the outer ones first, as the canonical
+/// path (to find include cycles) and the path given
+#[derive(Default, Debug)]
+pub struct OpenFiles {
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl OpenFiles {
+    /// Canonical path and contents of the tasks file, unless it is
+    /// already open, which would be an include cycle
+    pub fn open(&self, path: &Path) -> Result<(PathBuf, String), String> {
+        let read_err = |e: std::io::Error| format!("Cannot read {}: {e}", path.display());
+        let full = path.canonicalize().map_err(read_err)?;
+        if let Some(pos) = self.files.iter().position(|(f, _)| *f == full) {
+            let chain: Vec<String> = self.files[pos..]
+                .iter()
+                .map(|(_, p)| p.display().to_string())
+                .chain([path.display().to_string()])
+                .collect();
+            return Err(format!("Include cycle: {}", chain.join(" -> ")));
+        }
+        let txt = std::fs::read_to_string(&full).map_err(read_err)?;
+        Ok((full, txt))
+    }
+
+    /// Mark the file opened with [`open`](Self::open) as being read,
+    /// until the matching [`pop`](Self::pop)
+    pub fn push(&mut self, full: PathBuf, path: &Path) {
+        self.files.push((full, path.to_path_buf()));
+    }
+
+    pub fn pop(&mut self) {
+        self.files.pop();
+    }
+
+    /// Directory of the innermost file, the files it includes are
+    /// relative to it
+    pub fn base(&self) -> &Path {
+        self.files
+            .last()
+            .and_then(|(_, path)| path.parent())
+            .unwrap_or(Path::new(""))
+    }
+}
+
+/// Problem with a statement, at the line and column (both 1-based)
+/// in the source where it is
+#[derive(Debug, Clone)]
+pub struct SourceError {
+    pub msg: String,
+    pub line: usize,
+    pub col: usize,
+}
+
+/// A complete top level statement in a tasks source
+#[derive(Debug, Clone, Copy)]
+pub struct Statement<'a> {
+    /// Line number (1-based) the statement starts on
+    pub line: usize,
+    /// Column (1-based) the statement starts on
+    pub col: usize,
+    /// Statement text, including any trailing comments on its lines
+    pub text: &'a str,
+}
+
+impl Statement<'_> {
+    /// Line and column in the source for the byte offset into the
+    /// statement text
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let before = &self.text[..offset];
+        match before.rfind('\n') {
+            Some(nl) => (
+                self.line + before.matches('\n').count(),
+                before[nl + 1..].chars().count() + 1,
+            ),
+            None => (self.line, self.col + before.chars().count()),
+        }
+    }
+
+    /// Line and column in the source for the line and column (both
+    /// 1-based) in the statement text
+    pub fn text_position(&self, line: usize, col: usize) -> (usize, usize) {
+        if line <= 1 {
+            (self.line, self.col + col.saturating_sub(1))
+        } else {
+            (self.line + line - 1, col)
+        }
+    }
+
+    /// Problem with the whole statement, shown at its start
+    pub fn error(&self, msg: String) -> SourceError {
+        SourceError {
+            msg,
+            line: self.line,
+            col: self.col,
+        }
+    }
+
+    /// Parse the statement into tasks; the error is shown at the token
+    /// the parser stopped at
+    pub fn parse(&self) -> Result<Vec<Task>, SourceError> {
+        let err = |e: ParseError| {
+            let (line, col) = self.text_position(e.line, e.col);
+            SourceError {
+                msg: e.user_msg(None),
+                line,
+                col,
+            }
+        };
+        let tokens = nadi_core::parser::tokenizer::get_tokens(self.text).map_err(err)?;
+        nadi_core::parser::tasks::parse(tokens).map_err(err)
+    }
+
+    /// Whether there is a comment anywhere inside the statement
+    pub fn has_comment(&self) -> bool {
+        let mut state = ScanState::default();
+        self.text.chars().any(|c| {
+            state.feed(c);
+            state.in_comment
+        })
+    }
+}
+
+/// Split the source into statements; a statement ends at the first
+/// newline where all the brackets and strings are closed. Blank and
+/// comment only lines are not part of any statement.
+pub fn statements(txt: &str) -> Vec<Statement<'_>> {
+    let mut stmts = Vec::new();
+    let mut state = ScanState::default();
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut start: Option<(usize, usize, usize)> = None;
+    for (i, c) in txt.char_indices() {
+        let was_comment = state.in_comment;
+        state.feed(c);
+        if c == '\n' {
+            if let Some((s, l, col)) = start {
+                if !state.is_open() {
+                    stmts.push(Statement {
+                        line: l,
+                        col,
+                        text: &txt[s..i],
+                    });
+                    start = None;
+                }
+            }
+            line += 1;
+            line_start = i + 1;
+        } else if start.is_none() && !was_comment && !state.in_comment && !c.is_whitespace() {
+            let col = txt[line_start..i].chars().count() + 1;
+            start = Some((i, line, col));
+        }
+    }
+    if let Some((s, l, col)) = start {
+        stmts.push(Statement {
+            line: l,
+            col,
+            text: &txt[s..],
+        });
+    }
+    stmts
+}
+
+/// Rough lexical token in a statement, enough to find the function
+/// calls and their arguments without the full parser
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lexeme {
+    Ident,
+    Str,
+    Literal,
+    Punct(char),
+}
+
+/// Lexemes in the text with their byte ranges
+fn lex(txt: &str) -> Vec<(usize, usize, Lexeme)> {
+    let mut lexemes = Vec::new();
+    let mut chars = txt.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let kind = match c {
+            _ if c.is_whitespace() => continue,
+            '#' => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                continue;
+            }
+            '"' => {
+                let mut escaped = false;
+                for (_, c) in chars.by_ref() {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => (),
+                    }
+                }
+                Lexeme::Str
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                while chars
+                    .next_if(|&(_, c)| c.is_alphanumeric() || c == '_')
+                    .is_some()
+                {}
+                Lexeme::Ident
+            }
+            _ if c.is_ascii_digit() => {
+                while chars
+                    .next_if(|&(_, c)| c.is_alphanumeric() || "_.:-+".contains(c))
+                    .is_some()
+                {}
+                Lexeme::Literal
+            }
+            _ => Lexeme::Punct(c),
+        };
+        let end = chars.peek().map(|&(i, _)| i).unwrap_or(txt.len());
+        lexemes.push((start, end, kind));
+    }
+    lexemes
+}
+
+/// Index of the bracket closing the one at `open`
+fn closing(lexemes: &[(usize, usize, Lexeme)], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, (_, _, lex)) in lexemes.iter().enumerate().skip(open) {
+        match lex {
+            Lexeme::Punct('(' | '[' | '{') => depth += 1,
+            Lexeme::Punct(')' | ']' | '}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CallType {
+    Node,
+    Network,
+}
+
+/// An argument in a function call; offsets are into the statement text
+#[derive(Debug, Clone, Copy)]
+pub enum CallArg<'a> {
+    Positional(usize),
+    Keyword(usize, &'a str),
+}
+
+/// A node or network function call in a statement
+#[derive(Debug, Clone)]
+pub struct FunctionCall<'a> {
+    pub ty: CallType,
+    pub name: &'a str,
+    /// Offset of the function name in the statement text
+    pub offset: usize,
+    pub args: Vec<CallArg<'a>>,
+    /// Node names in the `node[...]` selection, with their offsets
+    pub selection: Vec<(usize, &'a str)>,
+}
+
+impl<'a> Statement<'a> {
+    /// Contents of the string literal starting at the byte offset, the
+    /// escape sequences are kept as they are
+    pub fn string_at(&self, offset: usize) -> Option<&'a str> {
+        let rest = self.text.get(offset..)?.strip_prefix('"')?;
+        let mut escaped = false;
+        for (i, c) in rest.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => return Some(&rest[..i]),
+                _ => (),
+            }
+        }
+        None
+    }
+
+    /// File of an `include "common.tasks"` directive; `None` if the
+    /// statement is not a directive, and an error if it is malformed
+    pub fn include(&self) -> Option<Result<&'a str, String>> {
+        let lexemes = lex(self.text);
+        let &(start, end, Lexeme::Ident) = lexemes.first()? else {
+            return None;
+        };
+        if &self.text[start..end] != "include" {
+            return None;
+        }
+        let file = match lexemes[1..] {
+            [(start, _, Lexeme::Str)] => self.string_at(start),
+            _ => None,
+        };
+        Some(file.ok_or_else(|| "Expected `include \"FILE\"`".to_string()))
+    }
+
+    /// The node or network function called in this statement, either
+    /// directly or as the value of an attribute assignment
+    pub fn function_call(&self) -> Option<FunctionCall<'a>> {
+        let text = self.text;
+        let lexemes = lex(text);
+        let word = |i: usize| match lexemes.get(i) {
+            Some(&(s, e, Lexeme::Ident)) => Some(&text[s..e]),
+            _ => None,
+        };
+        let punct = |i: usize| match lexemes.get(i) {
+            Some(&(_, _, Lexeme::Punct(c))) => Some(c),
+            _ => None,
+        };
+        let ty = match word(0)? {
+            "node" => CallType::Node,
+            "network" => CallType::Network,
+            _ => return None,
+        };
+        let mut i = 1;
+        let mut selection = Vec::new();
+        if let Some(open @ ('(' | '[')) = punct(i) {
+            let close = closing(&lexemes, i)?;
+            if open == '[' {
+                selection = lexemes[(i + 1)..close]
+                    .iter()
+                    .filter_map(|&(s, e, lex)| match lex {
+                        Lexeme::Ident => Some((s, &text[s..e])),
+                        Lexeme::Str => Some((s, &text[(s + 1)..(e - 1)])),
+                        _ => None,
+                    })
+                    .collect();
+            }
+            i = close + 1;
+        }
+        if punct(i) == Some('.') {
+            // attribute assignment, the value might be a function call
+            if word(i + 1).is_none() || punct(i + 2) != Some('=') {
+                return None;
+            }
+            i += 3;
+        }
+        let (offset, _, _) = *lexemes.get(i)?;
+        let mut end = i;
+        while punct(end + 1) == Some('.') && word(end + 2).is_some() {
+            end += 2;
+        }
+        word(end)?;
+        let name = &text[offset..lexemes[end].1];
+        let open = end + 1;
+        if punct(open) != Some('(') {
+            return None;
+        }
+        let close = closing(&lexemes, open)?;
+
+        let mut args = Vec::new();
+        let mut depth = 0;
+        let mut arg_start = open + 1;
+        for (j, &(_, _, lex)) in lexemes.iter().enumerate().take(close + 1).skip(open + 1) {
+            match lex {
+                Lexeme::Punct('(' | '[' | '{') => depth += 1,
+                Lexeme::Punct(')' | ']' | '}') if depth > 0 => depth -= 1,
+                Lexeme::Punct(',' | ')') if depth == 0 => {
+                    if j > arg_start {
+                        let (s, _, _) = lexemes[arg_start];
+                        args.push(match (word(arg_start), punct(arg_start + 1)) {
+                            (Some(kw), Some('=')) => CallArg::Keyword(s, kw),
+                            _ => CallArg::Positional(s),
+                        });
+                    }
+                    arg_start = j + 1;
+                }
+                _ => (),
+            }
+        }
+        Some(FunctionCall {
+            ty,
+            name,
+            offset,
+            args,
+            selection,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_lines_and_columns() {
+        let txt = "# comment\n\nnode f()\n  network g(\n    1,\n    2\n  )\nenv.x = 1";
+        let stmts = statements(txt);
+        let found: Vec<_> = stmts.iter().map(|s| (s.line, s.col, s.text)).collect();
+        assert_eq!(
+            found,
+            [
+                (3, 1, "node f()"),
+                (4, 3, "network g(\n    1,\n    2\n  )"),
+                (8, 1, "env.x = 1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn statements_strings_and_comments() {
+        let txt = "env.x = \"a # (\" # trailing (\nenv.y = \"b\\\"\n)\"\n";
+        let stmts = statements(txt);
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(stmts[0].text, "env.x = \"a # (\" # trailing (");
+        assert!(stmts[0].has_comment());
+        assert_eq!(stmts[1].text, "env.y = \"b\\\"\n)\"");
+        assert!(!stmts[1].has_comment());
+    }
+
+    #[test]
+    fn incomplete_input() {
+        assert!(is_incomplete("node f(\n"));
+        assert!(is_incomplete("env.x = \"abc"));
+        assert!(!is_incomplete("node f() # (\n"));
+        assert!(!is_incomplete("env.x = [1, 2]"));
+    }
+
+    #[test]
+    fn position_in_statement() {
+        let stmt = Statement {
+            line: 4,
+            col: 3,
+            text: "network g(\n    1\n)",
+        };
+        assert_eq!(stmt.position(8), (4, 11));
+        assert_eq!(stmt.position(15), (5, 5));
+        assert_eq!(stmt.text_position(1, 9), (4, 11));
+        assert_eq!(stmt.text_position(2, 5), (5, 5));
+    }
+
+    #[test]
+    fn lexemes() {
+        let kinds: Vec<_> = lex("node.x = f(\"a\", 2020-01-02) # c")
+            .into_iter()
+            .map(|(_, _, l)| l)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                Lexeme::Ident,
+                Lexeme::Punct('.'),
+                Lexeme::Ident,
+                Lexeme::Punct('='),
+                Lexeme::Ident,
+                Lexeme::Punct('('),
+                Lexeme::Str,
+                Lexeme::Punct(','),
+                Lexeme::Literal,
+                Lexeme::Punct(')'),
+            ]
+        );
+        let txt = "f(\"a\\\"b\")";
+        let ranges: Vec<_> = lex(txt).into_iter().map(|(s, e, _)| &txt[s..e]).collect();
+        assert_eq!(ranges, ["f", "(", "\"a\\\"b\"", ")"]);
+    }
+
+    fn stmt(text: &str) -> Statement<'_> {
+        Statement {
+            line: 1,
+            col: 1,
+            text,
+        }
+    }
+
+    #[test]
+    fn function_calls() {
+        let s = stmt("node[a, \"b-c\"].x = render(\"t\", [1, 2], safe = true)");
+        let call = s.function_call().unwrap();
+        assert_eq!(call.ty, CallType::Node);
+        assert_eq!(call.name, "render");
+        assert_eq!(call.offset, 19);
+        assert_eq!(call.selection, [(5, "a"), (8, "b-c")]);
+        assert!(matches!(
+            call.args[..],
+            [
+                CallArg::Positional(26),
+                CallArg::Positional(31),
+                CallArg::Keyword(39, "safe")
+            ]
+        ));
+
+        let call = stmt("network gis.save(\"x\")").function_call().unwrap();
+        assert_eq!(call.ty, CallType::Network);
+        assert_eq!(call.name, "gis.save");
+
+        assert!(stmt("env.x = 1").function_call().is_none());
+        assert!(stmt("node.x = 1").function_call().is_none());
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = std::env::temp_dir().join(format!("nadi-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("a.tasks"), "include \"sub/b.tasks\"\n").unwrap();
+        std::fs::write(dir.join("sub/b.tasks"), "").unwrap();
+
+        let mut files = OpenFiles::default();
+        let a = dir.join("a.tasks");
+        let (full, _) = files.open(&a).unwrap();
+        files.push(full, &a);
+        assert_eq!(files.base(), dir);
+        let b = dir.join("sub/b.tasks");
+        let (full, _) = files.open(&b).unwrap();
+        files.push(full, &b);
+        // the same file through another path is still a cycle
+        let again = dir.join("sub/../a.tasks");
+        let err = files.open(&again).unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "Include cycle: {} -> {} -> {}",
+                a.display(),
+                b.display(),
+                again.display()
+            )
+        );
+        files.pop();
+        files.pop();
+        assert!(files.open(&again).is_ok());
+        assert!(files.open(&dir.join("missing.tasks")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_directive() {
+        assert_eq!(
+            stmt("include \"a/b.tasks\"").include(),
+            Some(Ok("a/b.tasks"))
+        );
+        assert!(matches!(stmt("include a").include(), Some(Err(_))));
+        assert!(matches!(
+            stmt("include \"a\" \"b\"").include(),
+            Some(Err(_))
+        ));
+        assert_eq!(stmt("node include()").include(), None);
+    }
+}
@@ -0,0 +1,135 @@
+//! Reading delimited text tables (CSV/TSV)
+
+use std::path::Path;
+
+/// Table read from a delimited text file
+pub struct Table {
+    pub header: Vec<String>,
+    /// Line number (1-based) and fields of each row
+    pub rows: Vec<(usize, Vec<String>)>,
+}
+
+impl Table {
+    /// Index of the column, the match is case insensitive
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.header
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Delimiter based on the file extension (in any case): tab for
+/// `.tsv`/`.tab`, comma for everything else
+pub fn delimiter(path: &Path) -> char {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .as_deref()
+    {
+        Some("tsv" | "tab") => '\t',
+        _ => ',',
+    }
+}
+
+/// Read the table with the first line as the header; fields can be
+/// quoted with `"`, and `""` inside a quoted field is a literal `"`
+pub fn read(txt: &str, delim: char) -> anyhow::Result<Table> {
+    let mut lines = txt
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+    let Some((_, header)) = lines.next() else {
+        anyhow::bail!("Empty table");
+    };
+    let header = split_row(header, delim);
+    let mut rows = Vec::new();
+    for (i, line) in lines {
+        let row = split_row(line, delim);
+        if row.len() != header.len() {
+            anyhow::bail!(
+                "Line {}: expected {} fields, found {}",
+                i + 1,
+                header.len(),
+                row.len()
+            );
+        }
+        rows.push((i + 1, row));
+    }
+    Ok(Table { header, rows })
+}
+
+/// Field as written in the table, quoted if it has the delimiter, a
+/// quote or a newline in it
+pub fn quote(field: &str, delim: char) -> String {
+    if field.contains([delim, '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn split_row(line: &str, delim: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            _ if c == delim && !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delimiter_from_extension() {
+        assert_eq!(delimiter(Path::new("edges.tsv")), '\t');
+        assert_eq!(delimiter(Path::new("edges.TSV")), '\t');
+        assert_eq!(delimiter(Path::new("edges.Tab")), '\t');
+        assert_eq!(delimiter(Path::new("edges.csv")), ',');
+        assert_eq!(delimiter(Path::new("edges")), ',');
+    }
+
+    #[test]
+    fn quoted_fields() {
+        let table = read("name,desc\n\na,\"x, \"\"y\"\"\"\n b , c \n", ',').unwrap();
+        assert_eq!(table.header, ["name", "desc"]);
+        assert_eq!(
+            table.rows,
+            [
+                (3, vec!["a".to_string(), "x, \"y\"".to_string()]),
+                (4, vec!["b".to_string(), "c".to_string()]),
+            ]
+        );
+        assert_eq!(table.column("DESC"), Some(1));
+    }
+
+    #[test]
+    fn quote_round_trip() {
+        assert_eq!(quote("plain", ','), "plain");
+        assert_eq!(quote("a\tb", ','), "a\tb");
+        assert_eq!(quote("a\tb", '\t'), "\"a\tb\"");
+        let fields = ["x, \"y\"", "z"].map(|f| quote(f, ','));
+        let table = read(&format!("a,b\n{}\n", fields.join(",")), ',').unwrap();
+        assert_eq!(table.rows[0].1, ["x, \"y\"", "z"]);
+    }
+
+    #[test]
+    fn field_count_mismatch() {
+        let err = read("a\tb\n1\t2\t3\n", '\t').err().unwrap();
+        assert_eq!(err.to_string(), "Line 2: expected 2 fields, found 3");
+        assert!(read("\n\n", ',').is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Re-run the tasks whenever their input files change
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::cli::RunArgs;
+use crate::source::{self, CallArg, CallType};
+
+/// How often the files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+/// Time without any further changes before re-running, so that the
+/// editors saving in multiple steps do not trigger multiple runs
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+pub fn watch(args: &RunArgs) -> anyhow::Result<()> {
+    loop {
+        let files = watched_files(args);
+        let stamps = modified(&files);
+        if let Err(e) = crate::run(args) {
+            eprintln!("Error: {e:?}");
+        }
+        eprintln!(
+            "** Watching {} **",
+            files
+                .iter()
+                .map(|f| f.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        let mut last = stamps.clone();
+        while last == stamps {
+            std::thread::sleep(POLL_INTERVAL);
+            last = modified(&files);
+        }
+        loop {
+            std::thread::sleep(DEBOUNCE);
+            let now = modified(&files);
+            if now == last {
+                break;
+            }
+            last = now;
+        }
+
+        if args.clear {
+            // clear the screen and move the cursor to the top
+            eprint!("\x1b[2J\x1b[H");
+        } else {
+            eprintln!("\n{}\n", "-".repeat(72));
+        }
+    }
+}
+
+/// Tasks files with the preludes and the files they include,
+/// connections file, attribute files and table, env file and the
+/// templates rendered by the `render` network function
+fn watched_files(args: &RunArgs) -> Vec<PathBuf> {
+    let mut tasks = Vec::new();
+    for file in args.prelude.iter().chain(&args.tasks) {
+        included_files(file, &mut source::OpenFiles::default(), &mut tasks);
+    }
+    let mut files: Vec<PathBuf> = tasks
+        .iter()
+        .chain(&args.network.network)
+        .chain(&args.network.attr_table)
+        .chain(&args.env_file)
+        .cloned()
+        .collect();
+    if let Some(ref dir) = args.network.attributes {
+        files.extend(crate::attrs::dir_files(dir).unwrap_or_default());
+    }
+    let mut sources: Vec<String> = args.task.iter().cloned().collect();
+    sources.extend(tasks.iter().filter_map(|t| std::fs::read_to_string(t).ok()));
+    for txt in &sources {
+        for stmt in source::statements(txt) {
+            let Some(call) = stmt.function_call() else {
+                continue;
+            };
+            let is_render = call.ty == CallType::Network
+                && (call.name == "render" || call.name.ends_with(".render"));
+            if !is_render {
+                continue;
+            }
+            if let Some(&CallArg::Positional(offset)) = call.args.first() {
+                if let Some(template) = stmt.string_at(offset) {
+                    let path = PathBuf::from(template);
+                    if path.exists() && !files.contains(&path) {
+                        files.push(path);
+                    }
+                }
+            }
+        }
+    }
+    files
+}
+
+/// Add the tasks file and the files it includes, each only once; the
+/// include cycles through other paths to a file end where the file is
+/// already open
+fn included_files(file: &Path, open: &mut source::OpenFiles, files: &mut Vec<PathBuf>) {
+    if files.iter().any(|f| f == file) {
+        return;
+    }
+    files.push(file.to_path_buf());
+    let Ok((full, txt)) = open.open(file) else {
+        return;
+    };
+    open.push(full, file);
+    let base = open.base().to_path_buf();
+    for stmt in source::statements(&txt) {
+        if let Some(Ok(inc)) = stmt.include() {
+            included_files(&base.join(inc), open, files);
+        }
+    }
+    open.pop();
+}
+
+fn modified(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files.iter().map(|f| mtime(f)).collect()
+}
+
+fn mtime(file: &Path) -> Option<SystemTime> {
+    std::fs::metadata(file).and_then(|m| m.modified()).ok()
+}